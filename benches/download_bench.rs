@@ -1,8 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion};
-use multhreadown::{
-    config::{Config, RetryConfig},
-    downloader,
-};
+use multhreadown::{config::Config, downloader};
 use tempfile::tempdir;
 use std::time::Duration;
 
@@ -15,7 +12,7 @@ fn download_benchmark(c: &mut Criterion) {
     group.warm_up_time(Duration::from_secs(3));  // 减少预热时间
     
     // 使用更小的测试文件
-    let test_files = vec![
+    let test_files = [
         // 使用单个小文件进行测试
         "https://raw.githubusercontent.com/rust-lang/rust/master/README.md",
     ];
@@ -25,21 +22,13 @@ fn download_benchmark(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let temp_dir = tempdir().unwrap();
-                let config = Config {
-                    download_dir: temp_dir.path().to_path_buf(),
-                    workers: 3,
-                    random_order: false,
-                    urls: test_files.iter().map(|&s| s.to_string()).collect(),
-                    rate_limit_kb: None,
-                    retry: RetryConfig {
-                        max_retries: 2,  // 减少重试次数
-                        initial_delay: 1,
-                        max_delay: 5,    // 减少最大延迟
-                        backoff_factor: 2.0,
-                    },
-                    concurrent_downloads: 3,
-                    connection_timeout: 10,  // 减少超时时间
-                };
+                let config = Config::new()
+                    .with_download_dir(temp_dir.path())
+                    .with_threads(3)
+                    .with_urls(test_files)
+                    .with_retry_attempts(2)  // 减少重试次数
+                    .with_retry_delay(Duration::from_secs(1))
+                    .with_connect_timeout(Duration::from_secs(10));
                 (config, temp_dir)
             },
             |(config, _temp_dir)| {
@@ -62,21 +51,13 @@ fn download_benchmark(c: &mut Criterion) {
         b.iter_with_setup(
             || {
                 let temp_dir = tempdir().unwrap();
-                let config = Config {
-                    download_dir: temp_dir.path().to_path_buf(),
-                    workers: 1,
-                    random_order: false,
-                    urls: test_files.iter().map(|&s| s.to_string()).collect(),
-                    rate_limit_kb: None,
-                    retry: RetryConfig {
-                        max_retries: 2,
-                        initial_delay: 1,
-                        max_delay: 5,
-                        backoff_factor: 2.0,
-                    },
-                    concurrent_downloads: 1,
-                    connection_timeout: 10,
-                };
+                let config = Config::new()
+                    .with_download_dir(temp_dir.path())
+                    .with_threads(1)
+                    .with_urls(test_files)
+                    .with_retry_attempts(2)
+                    .with_retry_delay(Duration::from_secs(1))
+                    .with_connect_timeout(Duration::from_secs(10));
                 (config, temp_dir)
             },
             |(config, _temp_dir)| {
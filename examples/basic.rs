@@ -1,14 +1,11 @@
 use multhreadown::{
     cache::CacheManager,
-    cli::{Command, DownloadStatus, InteractiveMode},
+    cli::InteractiveMode,
     config::{Config, RetryConfig},
+    control::DownloadControl,
     downloader,
-    events::DefaultEventHandler,
-    stats::DownloadStats,
 };
 use std::path::PathBuf;
-use std::sync::Arc;
-use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() {
@@ -30,81 +27,59 @@ async fn main() {
         retry: RetryConfig::default(),
         concurrent_downloads: 4,
         connection_timeout: 30,
+        ..Config::default()
     };
 
     // 初始化缓存管理器
     let cache_manager = CacheManager::new("./cache").await.unwrap();
     
-    // 初始化下载统计
-    let stats = Arc::new(DownloadStats::default());
-    let stats_clone = stats.clone();
-    
-    // 创建事件处理器
-    let event_handler = Arc::new(DefaultEventHandler);
+    // 下载控制句柄：暂停、恢复、取消和限速都通过它完成，统计信息也由它持有
+    let control = DownloadControl::new(config.rate_limit_kb);
 
-    // 创建交互模式
-    let (mut interactive_mode, status_tx, command_rx) = InteractiveMode::new();
+    // 创建交互模式，下载状态通过 status_tx 发回交互界面
+    let (mut interactive_mode, status_tx, mut command_rx) = InteractiveMode::new();
+    control.set_status_sender(status_tx);
 
     // 在单独的任务中运行交互模式
     let interactive_handle = tokio::spawn(async move {
         interactive_mode.run().await;
     });
 
-    // 创建命令处理通道
-    let (cmd_tx, mut cmd_rx) = mpsc::channel(10);
-    let cmd_tx_clone = cmd_tx.clone();
-
-    // 处理用户输入的命令
+    // 将用户输入的命令（pause / resume 2 / cancel / progress / limit 512）交给控制句柄
+    let command_control = control.clone();
     tokio::spawn(async move {
         while let Some(cmd) = command_rx.recv().await {
-            match cmd {
-                Command::Pause => println!("下载已暂停"),
-                Command::Resume => println!("下载已恢复"),
-                Command::Cancel => {
-                    println!("下载已取消");
-                    cmd_tx.send(Command::Cancel).await.ok();
-                }
-                Command::ShowProgress => {
-                    let total = stats_clone.total_bytes.load(std::sync::atomic::Ordering::SeqCst);
-                    let success = stats_clone.successful_downloads.load(std::sync::atomic::Ordering::SeqCst);
-                    let failed = stats_clone.failed_downloads.load(std::sync::atomic::Ordering::SeqCst);
-                    println!("下载进度：");
-                    println!("总大小：{} bytes", total);
-                    println!("成功：{} 个文件", success);
-                    println!("失败：{} 个文件", failed);
-                }
-                _ => {}
-            }
+            command_control.apply(cmd);
         }
     });
 
     // 启动下载
+    let download_control = control.clone();
     let download_handle = tokio::spawn(async move {
-        match downloader::download_all_files(config).await {
-            Ok(_) => {
-                status_tx.send(DownloadStatus::Completed).await.ok();
+        match downloader::download_all_files_with_control(config, download_control).await {
+            Ok(report) if report.is_success() => {
+                let summary = report.summary;
                 println!("所有文件下载完成！");
                 
                 // 显示最终统计信息
                 println!("\n下载统计：");
-                println!("总下载量：{} bytes", stats.total_bytes.load(std::sync::atomic::Ordering::SeqCst));
-                println!("成功数量：{}", stats.successful_downloads.load(std::sync::atomic::Ordering::SeqCst));
-                println!("失败数量：{}", stats.failed_downloads.load(std::sync::atomic::Ordering::SeqCst));
-                println!("重试次数：{}", stats.retry_count.load(std::sync::atomic::Ordering::SeqCst));
+                println!("总下载量：{} bytes", summary.total_bytes);
+                println!("成功数量：{}", summary.successful_downloads);
+                println!("失败数量：{}", summary.failed_downloads);
+                println!("重试次数：{}", summary.retry_count);
+                println!("平均速度：{} bytes/s", summary.average_speed);
                 
                 // 保存缓存
                 if let Err(e) = cache_manager.save().await {
                     eprintln!("缓存保存失败: {}", e);
                 }
             }
-            Err(e) => {
-                eprintln!("下载失败: {}", e);
-                status_tx.send(DownloadStatus::Failed(e.to_string())).await.ok();
-            }
+            Ok(report) => eprintln!("部分文件下载失败:\n{}", report),
+            Err(e) => eprintln!("下载失败: {}", e),
         }
     });
 
-    // 等待下载完成或用户取消
+    // 等待下载完成，超时则取消
     tokio::select! {
         _ = interactive_handle => {
             println!("交互模式已关闭");
@@ -112,12 +87,9 @@ async fn main() {
         _ = download_handle => {
             println!("下载任务已完成");
         }
-        Some(Command::Cancel) = cmd_rx.recv() => {
-            println!("正在取消下载...");
-            cmd_tx_clone.send(Command::Cancel).await.ok();
-        }
         _ = tokio::time::sleep(std::time::Duration::from_secs(300)) => {
-            println!("下载超时（5分钟）");
+            println!("下载超时（5分钟），正在取消...");
+            control.cancel();
         }
     }
 }
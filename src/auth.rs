@@ -0,0 +1,140 @@
+//! 认证信息：配置中的 `[auth]` 和 `.netrc` 文件
+//!
+//! 凭据不必写进配置文件：`[auth]` 的每个键都可以用环境变量设置，例如
+//! `MULTHREADOWN_AUTH__BEARER_TOKEN`；也可以开启 `netrc`，按主机名从 `.netrc` 中查找用户名和密码。
+//! `[auth]` 的凭据只发给 `hosts` 中列出的主机。
+
+use crate::config::AuthConfig;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::HeaderValue;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// 一台主机的登录信息
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Login {
+    pub login: Option<String>,
+    pub password: Option<String>,
+}
+
+/// 解析后的 `.netrc` 文件
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Netrc {
+    machines: HashMap<String, Login>,
+    default: Option<Login>,
+}
+
+impl Netrc {
+    /// 解析 `machine`、`default`、`login`、`password` 和 `account`，跳过 `macdef` 定义的宏
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut netrc = Netrc::default();
+        // 当前条目的主机名，`None` 表示 `default`
+        let mut current: Option<Option<String>> = None;
+        let mut lines = text.lines();
+
+        while let Some(line) = lines.next() {
+            let mut tokens = line.split_whitespace();
+            while let Some(token) = tokens.next() {
+                match token {
+                    "machine" => {
+                        let host = tokens
+                            .next()
+                            .ok_or_else(|| "missing host after machine".to_string())?;
+                        netrc.machines.entry(host.to_string()).or_default();
+                        current = Some(Some(host.to_string()));
+                    }
+                    "default" => {
+                        netrc.default.get_or_insert_with(Login::default);
+                        current = Some(None);
+                    }
+                    "login" | "password" | "account" => {
+                        let value = tokens
+                            .next()
+                            .ok_or_else(|| format!("missing value after {}", token))?;
+                        let entry = match &current {
+                            Some(Some(host)) => netrc.machines.get_mut(host),
+                            Some(None) => netrc.default.as_mut(),
+                            None => None,
+                        }
+                        .ok_or_else(|| format!("{} outside of a machine entry", token))?;
+                        match token {
+                            "login" => entry.login = Some(value.to_string()),
+                            "password" => entry.password = Some(value.to_string()),
+                            _ => {}
+                        }
+                    }
+                    // 宏定义持续到下一个空行
+                    "macdef" => {
+                        for line in lines.by_ref() {
+                            if line.trim().is_empty() {
+                                break;
+                            }
+                        }
+                        break;
+                    }
+                    token if token.starts_with('#') => break,
+                    other => return Err(format!("unexpected token {:?}", other)),
+                }
+            }
+        }
+        Ok(netrc)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), e),
+            )
+        })
+    }
+
+    /// `$NETRC`，否则为 `~/.netrc`
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = std::env::var_os("NETRC").filter(|p| !p.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".netrc"))
+    }
+
+    /// 主机名对应的条目，没有时使用 `default`
+    pub fn get(&self, host: &str) -> Option<&Login> {
+        self.machines.get(host).or(self.default.as_ref())
+    }
+}
+
+/// `url` 应使用的 `Authorization` 头：`[auth]` 中适用于该主机的凭据优先，其次是 `.netrc`
+pub fn authorization(
+    auth: Option<&AuthConfig>,
+    netrc: Option<&Netrc>,
+    url: &Url,
+) -> Option<HeaderValue> {
+    let host = url.host_str()?;
+    let value = match auth.filter(|auth| auth.applies_to(host)) {
+        Some(AuthConfig {
+            bearer_token: Some(token),
+            ..
+        }) => format!("Bearer {}", token),
+        Some(AuthConfig {
+            username: Some(username),
+            password,
+            ..
+        }) => basic(username, password.as_deref()),
+        _ => {
+            let login = netrc?.get(host)?;
+            basic(login.login.as_deref()?, login.password.as_deref())
+        }
+    };
+    let mut value = HeaderValue::from_str(&value).ok()?;
+    value.set_sensitive(true);
+    Some(value)
+}
+
+fn basic(username: &str, password: Option<&str>) -> String {
+    let credentials = format!("{}:{}", username, password.unwrap_or_default());
+    format!("Basic {}", STANDARD.encode(credentials))
+}
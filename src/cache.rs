@@ -1,9 +1,13 @@
+use crate::utils::part_meta_path;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::fs;
+use tokio::sync::Mutex;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadCache {
     pub url: String,
     pub file_size: u64,
@@ -11,11 +15,82 @@ pub struct DownloadCache {
     pub etag: Option<String>,
     pub last_modified: Option<String>,
     pub checksum: Option<String>,
+    /// 最终保存路径；旧版本缓存没有记录，按 URL 推算
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
 }
 
+impl DownloadCache {
+    /// 本地文件已包含远程文件的全部内容
+    pub fn is_complete(&self) -> bool {
+        self.file_size > 0 && self.downloaded_size == self.file_size
+    }
+}
+
+/// `.part` 旁的元数据文件（`.part.meta`）：写入数据时远程文件的版本，分段下载和预分配的
+/// 单连接下载还记录进度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct PartMeta {
+    #[serde(flatten)]
+    pub entry: DownloadCache,
+    /// 单连接下载为空
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub segments: Vec<SegmentProgress>,
+    /// 预分配的单连接下载从头起已落盘的字节数；为 `None` 时进度即 `.part` 的长度
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub written: Option<u64>,
+}
+
+impl PartMeta {
+    /// 读取 `part_path` 旁的元数据，不存在或无法解析时返回 `None`
+    pub fn read(part_path: &Path) -> Option<Self> {
+        let content = std::fs::read(part_meta_path(part_path)).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    /// `.part` 中可信的字节数；分段下载和预分配的 `.part` 长度不代表进度
+    pub fn downloaded(&self, part_len: u64) -> u64 {
+        if self.segments.is_empty() {
+            self.written.map_or(part_len, |written| written.min(part_len))
+        } else {
+            self.segments.iter().map(|s| s.written).sum()
+        }
+    }
+}
+
+/// 分段下载中一个分段的进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SegmentProgress {
+    pub start: u64,
+    /// 最后一个字节的偏移（含）
+    pub end: u64,
+    /// 从 `start` 起已落盘的字节数
+    pub written: u64,
+}
+
+impl SegmentProgress {
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// 两次保存之间的最短间隔；间隔内的修改只更新内存，由之后的保存或 [`CacheManager::save`] 写入
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+const CACHE_FILE: &str = "download_cache.json";
+
 pub struct CacheManager {
     cache_dir: PathBuf,
     cache: HashMap<String, DownloadCache>,
+    /// 保存路径到记录了该路径的 URL 的索引
+    paths: HashMap<PathBuf, HashSet<String>>,
+    /// 每次修改加一，用于判断是否有未保存的修改
+    revision: u64,
+    /// 最近一次生成快照时的 `revision`
+    snapshot_revision: u64,
+    last_save: Option<Instant>,
+    /// 已写入磁盘的最新 `revision`，保证较旧的快照不会覆盖较新的
+    written: Arc<Mutex<u64>>,
 }
 
 impl CacheManager {
@@ -23,15 +98,43 @@ impl CacheManager {
         let cache_dir = cache_dir.as_ref().to_owned();
         fs::create_dir_all(&cache_dir).await?;
         
-        let cache_file = cache_dir.join("download_cache.json");
-        let cache = if cache_file.exists() {
+        let cache_file = cache_dir.join(CACHE_FILE);
+        let cache: HashMap<String, DownloadCache> = if cache_file.exists() {
             let content = fs::read_to_string(&cache_file).await?;
-            serde_json::from_str(&content).unwrap_or_default()
+            match serde_json::from_str(&content) {
+                Ok(cache) => cache,
+                Err(e) => {
+                    // 保留损坏的文件，不让下一次保存悄悄覆盖它
+                    let backup = cache_dir.join(format!("{}.corrupt", CACHE_FILE));
+                    log::warn!(
+                        "Download cache {} is corrupt ({}), moved to {} and starting empty",
+                        cache_file.display(),
+                        e,
+                        backup.display()
+                    );
+                    fs::rename(&cache_file, &backup).await?;
+                    HashMap::new()
+                }
+            }
         } else {
             HashMap::new()
         };
 
-        Ok(Self { cache_dir, cache })
+        let mut paths: HashMap<PathBuf, HashSet<String>> = HashMap::new();
+        for (url, entry) in &cache {
+            if let Some(path) = &entry.path {
+                paths.entry(path.clone()).or_default().insert(url.clone());
+            }
+        }
+        Ok(Self {
+            cache_dir,
+            cache,
+            paths,
+            revision: 0,
+            snapshot_revision: 0,
+            last_save: None,
+            written: Arc::new(Mutex::new(0)),
+        })
     }
 
     pub fn get_cache(&self, url: &str) -> Option<&DownloadCache> {
@@ -39,12 +142,89 @@ impl CacheManager {
     }
 
     pub fn update_cache(&mut self, url: String, cache: DownloadCache) {
-        self.cache.insert(url, cache);
+        let path = cache.path.clone();
+        if let Some(old) = self.cache.insert(url.clone(), cache) {
+            self.unindex(&url, old.path.as_deref());
+        }
+        if let Some(path) = path {
+            self.paths.entry(path).or_default().insert(url);
+        }
+        self.revision += 1;
     }
 
+    pub fn entries(&self) -> impl Iterator<Item = &DownloadCache> {
+        self.cache.values()
+    }
+
+    /// 记录了保存路径为 `path` 的 URL
+    pub fn urls_at(&self, path: &Path) -> impl Iterator<Item = &str> {
+        self.paths.get(path).into_iter().flatten().map(String::as_str)
+    }
+
+    pub fn remove_cache(&mut self, url: &str) -> Option<DownloadCache> {
+        let removed = self.cache.remove(url)?;
+        self.unindex(url, removed.path.as_deref());
+        self.revision += 1;
+        Some(removed)
+    }
+
+    fn unindex(&mut self, url: &str, path: Option<&Path>) {
+        let Some(path) = path else { return };
+        if let Some(urls) = self.paths.get_mut(path) {
+            urls.remove(url);
+            if urls.is_empty() {
+                self.paths.remove(path);
+            }
+        }
+    }
+
+    /// 有未保存的修改且距上次保存已超过 `interval` 时返回当前内容的快照。
+    /// 快照可以在释放缓存的锁之后再写入
+    pub fn snapshot(&mut self, interval: Duration) -> std::io::Result<Option<CacheSnapshot>> {
+        if self.revision == self.snapshot_revision
+            || self.last_save.is_some_and(|at| at.elapsed() < interval)
+        {
+            return Ok(None);
+        }
+        self.snapshot_revision = self.revision;
+        self.last_save = Some(Instant::now());
+        self.current().map(Some)
+    }
+
+    /// 立即写入所有未保存的修改
     pub async fn save(&self) -> std::io::Result<()> {
-        let cache_file = self.cache_dir.join("download_cache.json");
-        let content = serde_json::to_string_pretty(&self.cache)?;
-        fs::write(cache_file, content).await
+        self.current()?.write().await
+    }
+
+    fn current(&self) -> std::io::Result<CacheSnapshot> {
+        Ok(CacheSnapshot {
+            cache_file: self.cache_dir.join(CACHE_FILE),
+            content: serde_json::to_vec(&self.cache)?,
+            revision: self.revision,
+            written: self.written.clone(),
+        })
+    }
+}
+
+/// [`CacheManager::snapshot`] 得到的缓存内容
+pub struct CacheSnapshot {
+    cache_file: PathBuf,
+    content: Vec<u8>,
+    revision: u64,
+    written: Arc<Mutex<u64>>,
+}
+
+impl CacheSnapshot {
+    /// 先写临时文件再重命名，中断的写入不会损坏已有的缓存文件
+    pub async fn write(self) -> std::io::Result<()> {
+        let mut written = self.written.lock().await;
+        if *written >= self.revision {
+            return Ok(());
+        }
+        let temp_file = self.cache_file.with_extension("json.tmp");
+        fs::write(&temp_file, &self.content).await?;
+        fs::rename(&temp_file, &self.cache_file).await?;
+        *written = self.revision;
+        Ok(())
     }
 } 
\ No newline at end of file
@@ -0,0 +1,103 @@
+use crate::config::ChecksumAlgorithm;
+use sha2::{Digest as _, Sha256, Sha512};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// 增量计算文件摘要，下载时边写入边更新，避免完成后重新读取整个文件
+pub struct Digest {
+    inner: Inner,
+}
+
+enum Inner {
+    Md5(md5::Context),
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl Digest {
+    pub fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let inner = match algorithm {
+            ChecksumAlgorithm::MD5 => Inner::Md5(md5::Context::new()),
+            ChecksumAlgorithm::SHA256 => Inner::Sha256(Sha256::new()),
+            ChecksumAlgorithm::SHA512 => Inner::Sha512(Sha512::new()),
+        };
+        Self { inner }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.inner {
+            Inner::Md5(ctx) => ctx.consume(data),
+            Inner::Sha256(hasher) => hasher.update(data),
+            Inner::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    /// 小写十六进制摘要
+    pub fn finalize(self) -> String {
+        match self.inner {
+            Inner::Md5(ctx) => format!("{:x}", ctx.compute()),
+            Inner::Sha256(hasher) => to_hex(&hasher.finalize()),
+            Inner::Sha512(hasher) => to_hex(&hasher.finalize()),
+        }
+    }
+}
+
+/// 读取 `path` 的前 `len` 字节计入摘要，`None` 表示读取整个文件
+pub fn update_from_file(digest: &mut Digest, path: &Path, len: Option<u64>) -> io::Result<()> {
+    let file = File::open(path)?;
+    let mut reader: Box<dyn Read> = match len {
+        Some(len) => Box::new(file.take(len)),
+        None => Box::new(file),
+    };
+    let mut buffer = [0; 64 * 1024];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            return Ok(());
+        }
+        digest.update(&buffer[..bytes_read]);
+    }
+}
+
+pub fn digest_file(path: &Path, algorithm: ChecksumAlgorithm) -> io::Result<String> {
+    let mut digest = Digest::new(algorithm);
+    update_from_file(&mut digest, path, None)?;
+    Ok(digest.finalize())
+}
+
+/// 缓存中保存的摘要带上算法名，例如 `sha256:9f86d0…`，之后无需配置也能重新校验
+pub fn tagged(algorithm: ChecksumAlgorithm, hex: &str) -> String {
+    format!("{}:{}", algorithm_name(algorithm), hex)
+}
+
+/// 解析 [`tagged`] 生成的字符串
+pub fn parse_tagged(value: &str) -> Option<(ChecksumAlgorithm, &str)> {
+    let (name, hex) = value.split_once(':')?;
+    Some((algorithm_from_name(name)?, hex))
+}
+
+/// 按名称查找算法，不区分大小写，也接受 aria2 的写法，例如 `sha-256`
+pub fn algorithm_from_name(name: &str) -> Option<ChecksumAlgorithm> {
+    let name = name.replace('-', "");
+    [
+        ChecksumAlgorithm::MD5,
+        ChecksumAlgorithm::SHA256,
+        ChecksumAlgorithm::SHA512,
+    ]
+    .into_iter()
+    .find(|&a| algorithm_name(a).eq_ignore_ascii_case(&name))
+}
+
+fn algorithm_name(algorithm: ChecksumAlgorithm) -> &'static str {
+    match algorithm {
+        ChecksumAlgorithm::MD5 => "md5",
+        ChecksumAlgorithm::SHA256 => "sha256",
+        ChecksumAlgorithm::SHA512 => "sha512",
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
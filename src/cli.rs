@@ -1,3 +1,4 @@
+use crate::stats::DownloadSummary;
 use tokio::sync::mpsc;
 
 #[derive(Debug, Clone)]
@@ -6,7 +7,12 @@ pub enum Command {
     Resume,
     Cancel,
     ShowProgress,
+    /// 总带宽上限（KB/s），0 表示取消限速
     SetRateLimit(u64),
+    /// 以下命令只作用于本批中指定序号的 URL
+    PauseFile(usize),
+    ResumeFile(usize),
+    CancelFile(usize),
 }
 
 #[derive(Debug, Clone)]
@@ -15,6 +21,13 @@ pub enum DownloadStatus {
     Paused,
     Completed,
     Failed(String),
+    Cancelled,
+    FilePaused(usize),
+    FileResumed(usize),
+    FileCancelled(usize),
+    Progress(DownloadSummary),
+    /// 调整后的限速，`None` 表示不限速
+    RateLimit(Option<u64>),
 }
 
 pub struct InteractiveMode {
@@ -49,7 +62,7 @@ impl InteractiveMode {
                         if n == 0 {
                             break;
                         }
-                        self.handle_command(&line.trim()).await;
+                        self.handle_command(line.trim()).await;
                         line.clear();
                     }
                 }
@@ -61,17 +74,21 @@ impl InteractiveMode {
     }
 
     async fn handle_command(&self, cmd: &str) {
-        let command = match cmd.to_lowercase().as_str() {
-            "pause" => Some(Command::Pause),
-            "resume" => Some(Command::Resume),
-            "cancel" => Some(Command::Cancel),
-            "progress" => Some(Command::ShowProgress),
-            _ if cmd.starts_with("limit ") => {
-                cmd.split_whitespace()
-                    .nth(1)
-                    .and_then(|s| s.parse().ok())
-                    .map(Command::SetRateLimit)
-            }
+        let lower = cmd.to_lowercase();
+        let mut words = lower.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        // 可选参数：URL 序号，`limit` 命令则是 KB/s
+        let arg = words.next().map(|s| s.parse::<u64>());
+
+        let command = match (name, arg) {
+            ("pause", None) => Some(Command::Pause),
+            ("resume", None) => Some(Command::Resume),
+            ("cancel", None) => Some(Command::Cancel),
+            ("progress", None) => Some(Command::ShowProgress),
+            ("pause", Some(Ok(index))) => Some(Command::PauseFile(index as usize)),
+            ("resume", Some(Ok(index))) => Some(Command::ResumeFile(index as usize)),
+            ("cancel", Some(Ok(index))) => Some(Command::CancelFile(index as usize)),
+            ("limit", Some(Ok(kb))) => Some(Command::SetRateLimit(kb)),
             _ => {
                 println!("Unknown command: {}", cmd);
                 None
@@ -91,6 +108,13 @@ impl InteractiveMode {
             DownloadStatus::Paused => println!("Download is paused"),
             DownloadStatus::Completed => println!("Download completed"),
             DownloadStatus::Failed(err) => println!("Download failed: {}", err),
+            DownloadStatus::Cancelled => println!("Download cancelled"),
+            DownloadStatus::FilePaused(index) => println!("File {} is paused", index),
+            DownloadStatus::FileResumed(index) => println!("File {} resumed", index),
+            DownloadStatus::FileCancelled(index) => println!("File {} cancelled", index),
+            DownloadStatus::Progress(summary) => println!("Progress: {}", summary),
+            DownloadStatus::RateLimit(Some(kb)) => println!("Rate limit set to {} KB/s", kb),
+            DownloadStatus::RateLimit(None) => println!("Rate limit removed"),
         }
     }
 } 
\ No newline at end of file
@@ -1,8 +1,11 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 use thiserror::Error;
 use std::collections::HashMap;
+use crate::template::OutputTemplate;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RetryConfig {
@@ -10,6 +13,9 @@ pub struct RetryConfig {
     pub initial_delay: u64,
     pub max_delay: u64,
     pub backoff_factor: f64,
+    /// Randomize each delay between half and the full computed value.
+    #[serde(default)]
+    pub jitter: bool,
 }
 
 impl Default for RetryConfig {
@@ -19,11 +25,30 @@ impl Default for RetryConfig {
             initial_delay: 1,
             max_delay: 30,
             backoff_factor: 2.0,
+            jitter: false,
         }
     }
 }
 
+impl RetryConfig {
+    /// Delay before retry number `attempt` (starting at 1): `initial_delay` grown by
+    /// `backoff_factor` per attempt and capped at `max_delay` seconds.
+    pub fn delay_for(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let secs = (self.initial_delay as f64 * self.backoff_factor.powi(exponent))
+            .min(self.max_delay as f64);
+
+        let secs = if self.jitter && secs > 0.0 {
+            rand::thread_rng().gen_range(secs / 2.0..=secs)
+        } else {
+            secs
+        };
+        Duration::from_secs_f64(secs)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub download_dir: PathBuf,
     pub workers: usize,
@@ -32,7 +57,100 @@ pub struct Config {
     pub rate_limit_kb: Option<u64>,
     pub retry: RetryConfig,
     pub concurrent_downloads: usize,
+    /// Seconds allowed for establishing a connection; 0 waits indefinitely.
     pub connection_timeout: u64,
+    /// Seconds without receiving any data before a request is abandoned and retried
+    /// from where it stopped; 0 disables the check.
+    #[serde(default = "default_read_timeout")]
+    pub read_timeout: u64,
+    /// Overall limit in seconds for one file, counted from the start of its transfer and
+    /// including retries.
+    #[serde(default)]
+    pub file_timeout: Option<u64>,
+    /// Overall limit in seconds for a whole batch; downloads still running are failed.
+    #[serde(default)]
+    pub batch_timeout: Option<u64>,
+    /// Maximum number of parallel range requests used for a single file.
+    #[serde(default = "default_segments")]
+    pub segments: usize,
+    /// Files smaller than twice this many bytes are fetched over one connection.
+    #[serde(default = "default_min_segment_size")]
+    pub min_segment_size: u64,
+    /// Upper bound on simultaneous requests to one host (name and port), counting every
+    /// segment and probe. Files from other hosts are started first while a host is at
+    /// its limit. Unlimited when unset.
+    #[serde(default)]
+    pub max_connections_per_host: Option<usize>,
+    /// Where resume metadata is kept; defaults to `<download_dir>/.multhreadown`.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub integrity_check: Option<IntegrityCheck>,
+    /// URLs rejected by the filter are skipped and reported with the reason.
+    #[serde(default)]
+    pub filter: Option<DownloadFilter>,
+    /// `User-Agent` sent with every request; reqwest's default when unset.
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// What to do when the resolved file name is already taken by another download.
+    #[serde(default)]
+    pub on_collision: CollisionPolicy,
+    /// Path of each file relative to `download_dir`, e.g. `{host}/{path}`; see
+    /// [`template`](crate::template) for the fields. Files land flat when unset.
+    #[serde(default)]
+    pub output_template: Option<String>,
+    /// Probe every URL before downloading so the total size and ETA are known up front.
+    /// The whole input is read first, and files filtered by size are never requested.
+    #[serde(default)]
+    pub preflight: bool,
+    /// Refuse to start downloads whose known size exceeds the free space of their directory.
+    /// Space still owed to downloads running on the same filesystem counts as used.
+    #[serde(default = "default_check_disk_space")]
+    pub check_disk_space: bool,
+    /// Reserve the blocks of a download up front. Segmented downloads otherwise write a sparse
+    /// file; single-stream downloads are only preallocated when the server reports their size,
+    /// and then record their resume offset in `.part.meta` instead of using the file length.
+    #[serde(default)]
+    pub preallocate: bool,
+    /// Extra request headers sent with every request.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Extra request headers for URLs under the key, see [`url_prefix_matches`]; longer
+    /// prefixes win.
+    #[serde(default)]
+    pub url_headers: HashMap<String, HashMap<String, String>>,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    /// Look up Basic credentials in `.netrc` for hosts not covered by `auth`.
+    #[serde(default)]
+    pub netrc: bool,
+    /// Defaults to `$NETRC`, then `~/.netrc`.
+    #[serde(default)]
+    pub netrc_file: Option<PathBuf>,
+    /// Netscape-format cookie file loaded when the client is built.
+    #[serde(default)]
+    pub cookie_file: Option<PathBuf>,
+    /// Explicit proxy settings; reqwest's `HTTP_PROXY`/`HTTPS_PROXY` handling applies when unset.
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+fn default_segments() -> usize {
+    4
+}
+
+fn default_min_segment_size() -> u64 {
+    1024 * 1024
+}
+
+fn default_read_timeout() -> u64 {
+    60
+}
+
+fn default_check_disk_space() -> bool {
+    true
 }
 
 impl Default for Config {
@@ -46,38 +164,356 @@ impl Default for Config {
             retry: RetryConfig::default(),
             concurrent_downloads: 4,
             connection_timeout: 30,
+            read_timeout: default_read_timeout(),
+            file_timeout: None,
+            batch_timeout: None,
+            segments: default_segments(),
+            min_segment_size: default_min_segment_size(),
+            max_connections_per_host: None,
+            cache_dir: None,
+            integrity_check: None,
+            filter: None,
+            user_agent: None,
+            on_collision: CollisionPolicy::default(),
+            output_template: None,
+            preflight: false,
+            check_disk_space: default_check_disk_space(),
+            preallocate: false,
+            headers: HashMap::new(),
+            url_headers: HashMap::new(),
+            auth: None,
+            netrc: false,
+            netrc_file: None,
+            cookie_file: None,
+            proxy: None,
+            tls: None,
+        }
+    }
+}
+
+/// Value of a proxy override that bypasses every proxy.
+pub const DIRECT: &str = "direct";
+
+/// Proxy for outgoing requests. URLs may use the `http`, `https`, `socks5` or `socks5h`
+/// scheme, and may carry their own `user:password@` credentials.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// Proxy used for every request not matched by `no_proxy` or `overrides`.
+    pub url: Option<String>,
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// Hosts reached directly: names (which also match their subdomains), IP addresses
+    /// or CIDR ranges such as `10.0.0.0/8`.
+    pub no_proxy: Vec<String>,
+    /// Proxy for URLs under the key (see [`url_prefix_matches`]), or `"direct"`; longer
+    /// prefixes win.
+    pub overrides: HashMap<String, String>,
+}
+
+impl ProxyConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: Some(url.into()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn with_no_proxy<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.no_proxy = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn with_override(mut self, prefix: impl Into<String>, proxy: impl Into<String>) -> Self {
+        self.overrides.insert(prefix.into(), proxy.into());
+        self
+    }
+
+    /// The override for `url`, if any.
+    pub fn override_for(&self, url: &str) -> Option<&str> {
+        self.overrides
+            .iter()
+            .filter(|(prefix, _)| url_prefix_matches(prefix, url))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, proxy)| proxy.as_str())
+    }
+}
+
+/// Checks that `proxy` is `"direct"` or a URL with a supported proxy scheme.
+pub fn validate_proxy(proxy: &str) -> Result<(), ConfigError> {
+    if proxy == DIRECT {
+        return Ok(());
+    }
+    let url = url::Url::parse(proxy)
+        .map_err(|e| ConfigError::InvalidProxy(format!("{:?}: {}", proxy, e)))?;
+    if !matches!(url.scheme(), "http" | "https" | "socks5" | "socks5h") {
+        return Err(ConfigError::InvalidProxy(format!(
+            "unsupported proxy scheme {:?}",
+            url.scheme()
+        )));
+    }
+    Ok(())
+}
+
+/// TLS settings for servers with a private CA or that require client certificates.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM files with root certificates trusted in addition to the system store.
+    pub ca_files: Vec<PathBuf>,
+    /// PEM certificate chain presented to servers that ask for one.
+    pub client_cert: Option<PathBuf>,
+    /// PKCS#8 PEM private key for `client_cert`; defaults to the `client_cert` file.
+    pub client_key: Option<PathBuf>,
+    /// Lowest accepted protocol version: `"1.0"`, `"1.1"` or `"1.2"`.
+    pub min_version: Option<String>,
+    /// Skip certificate and host name verification entirely. Only for lab setups.
+    pub danger_accept_invalid_certs: bool,
+}
+
+impl TlsConfig {
+    pub fn with_ca_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.ca_files.push(path.into());
+        self
+    }
+
+    pub fn with_client_cert(mut self, cert: impl Into<PathBuf>, key: Option<PathBuf>) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = key;
+        self
+    }
+
+    pub fn with_min_version(mut self, version: impl Into<String>) -> Self {
+        self.min_version = Some(version.into());
+        self
+    }
+
+    pub fn with_danger_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.danger_accept_invalid_certs = accept;
+        self
+    }
+}
+
+/// Checks that `version` names a minimum TLS version the TLS backend can enforce.
+pub fn validate_tls_version(version: &str) -> Result<(), ConfigError> {
+    match version {
+        "1.0" | "1.1" | "1.2" => Ok(()),
+        "1.3" => Err(ConfigError::InvalidTls(
+            "the native TLS backend cannot require TLS 1.3 as the minimum".to_string(),
+        )),
+        other => Err(ConfigError::InvalidTls(format!(
+            "unknown TLS version {:?}, expected 1.0, 1.1 or 1.2",
+            other
+        ))),
+    }
+}
+
+/// Credentials sent as the `Authorization` header. Secrets are never written out by
+/// `--print-config`; set them with `MULTHREADOWN_AUTH__BEARER_TOKEN` or
+/// `MULTHREADOWN_AUTH__PASSWORD` to keep them out of config files.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Hosts that receive the credentials. Required: credentials are never sent to
+    /// every host by default.
+    pub hosts: Vec<String>,
+    /// Basic authentication.
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    /// Sent as `Authorization: Bearer <token>`; takes precedence over `username`.
+    #[serde(skip_serializing)]
+    pub bearer_token: Option<String>,
+}
+
+impl AuthConfig {
+    pub fn bearer(token: impl Into<String>) -> Self {
+        Self {
+            bearer_token: Some(token.into()),
+            ..Self::default()
         }
     }
+
+    pub fn basic(username: impl Into<String>, password: impl Into<String>) -> Self {
+        Self {
+            username: Some(username.into()),
+            password: Some(password.into()),
+            ..Self::default()
+        }
+    }
+
+    /// Restricts the credentials to the given hosts.
+    pub fn for_hosts<I, S>(mut self, hosts: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.hosts = hosts.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn applies_to(&self, host: &str) -> bool {
+        self.hosts.iter().any(|h| h.eq_ignore_ascii_case(host))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IntegrityCheck {
     pub enabled: bool,
     pub algorithm: ChecksumAlgorithm,
+    /// Expected hex digest keyed by URL.
     pub checksums: HashMap<String, String>,
+    /// What to do with a file whose digest does not match.
+    #[serde(default)]
+    pub on_mismatch: MismatchPolicy,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl IntegrityCheck {
+    pub fn expected_checksum(&self, url: &str) -> Option<&str> {
+        if !self.enabled {
+            return None;
+        }
+        self.checksums.get(url).map(|s| s.trim())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ChecksumAlgorithm {
     MD5,
     SHA256,
     SHA512,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MismatchPolicy {
+    /// Remove the corrupted download.
+    #[default]
+    Delete,
+    /// Move the corrupted download into `<cache_dir>/quarantine` for inspection.
+    Quarantine,
+}
+
+/// Handling of a target path that another URL already downloaded to, or is downloading
+/// to in the same run. A file without any download record is assumed to be an earlier
+/// download of the same URL and is skipped as complete.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionPolicy {
+    /// Replace the file left by an earlier run. Two URLs of the same run never share a
+    /// file; the later one fails instead.
+    Overwrite,
+    /// Leave the existing file alone and report the URL as skipped.
+    Skip,
+    /// Save as `name (1).ext`, `name (2).ext`, ... until a free name is found.
+    #[default]
+    Rename,
+    /// Fail the download.
+    Error,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DownloadFilter {
+    /// Glob patterns matched against the file name; empty means everything is included.
     pub include_patterns: Vec<String>,
     pub exclude_patterns: Vec<String>,
     pub min_size: Option<u64>,
     pub max_size: Option<u64>,
 }
 
+impl DownloadFilter {
+    /// Returns why `file_name` is filtered out, or `None` if it should be downloaded.
+    pub fn reject_name(&self, file_name: &str) -> Option<String> {
+        let matches = |pattern: &String| {
+            glob::Pattern::new(pattern).is_ok_and(|p| p.matches(file_name))
+        };
+
+        if let Some(pattern) = self.exclude_patterns.iter().find(|p| matches(p)) {
+            return Some(format!("excluded by pattern {}", pattern));
+        }
+        if !self.include_patterns.is_empty() && !self.include_patterns.iter().any(matches) {
+            return Some("does not match any include pattern".to_string());
+        }
+        None
+    }
+
+    /// Returns why a file of `size` bytes is filtered out, or `None` if it is within limits.
+    pub fn reject_size(&self, size: u64) -> Option<String> {
+        if let Some(min) = self.min_size.filter(|&min| size < min) {
+            return Some(format!(
+                "{} is below min_size {}",
+                bytesize::to_string(size, true),
+                bytesize::to_string(min, true)
+            ));
+        }
+        self.reject_oversize(size)
+    }
+
+    /// Like [`reject_size`](Self::reject_size) but only checks `max_size`, for sizes
+    /// that are still growing during a download.
+    pub fn reject_oversize(&self, size: u64) -> Option<String> {
+        self.max_size.filter(|&max| size > max).map(|max| {
+            format!(
+                "{} exceeds max_size {}",
+                bytesize::to_string(size, true),
+                bytesize::to_string(max, true)
+            )
+        })
+    }
+
+    pub fn has_size_limits(&self) -> bool {
+        self.min_size.is_some() || self.max_size.is_some()
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("Invalid download directory: {0}")]
     InvalidDownloadDir(String),
     #[error("Invalid number of workers: {0}")]
     InvalidWorkers(usize),
+    #[error("Invalid number of segments: {0}")]
+    InvalidSegments(usize),
+    #[error("Invalid number of connections per host: {0}")]
+    InvalidConnections(usize),
+    #[error("Invalid retry settings: {0}")]
+    InvalidRetry(String),
+    #[error("Invalid timeout: {0}")]
+    InvalidTimeout(String),
+    #[error("Invalid filter: {0}")]
+    InvalidFilter(String),
+    #[error("Invalid output template: {0}")]
+    InvalidTemplate(String),
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("Invalid auth settings: {0}")]
+    InvalidAuth(String),
+    #[error("Invalid proxy: {0}")]
+    InvalidProxy(String),
+    #[error("Invalid TLS settings: {0}")]
+    InvalidTls(String),
+    #[error("Invalid config file {0}: {1}")]
+    InvalidFile(PathBuf, String),
+    #[error("Invalid environment variable {0}: {1}")]
+    InvalidEnv(String, String),
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
     #[error("No download URLs provided")]
     NoUrls,
     #[error("Invalid URL format: {0}")]
@@ -85,9 +521,188 @@ pub enum ConfigError {
 }
 
 impl Config {
-    pub fn validate(&self) -> Result<(), ConfigError> {
+    /// Same as [`Config::default`]; starting point for the `with_*` methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_download_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.download_dir = dir.into();
+        self
+    }
+
+    pub fn with_urls<I, S>(mut self, urls: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.urls = urls.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Number of files downloaded at the same time.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.workers = threads;
+        self.concurrent_downloads = threads;
+        self
+    }
+
+    /// Parallel range requests per file.
+    pub fn with_segments(mut self, segments: usize) -> Self {
+        self.segments = segments;
+        self
+    }
+
+    /// Limit simultaneous requests per host; `None` removes the limit.
+    pub fn with_max_connections_per_host(mut self, limit: Option<usize>) -> Self {
+        self.max_connections_per_host = limit;
+        self
+    }
+
+    /// Minimum size of each range request when a file is split into segments.
+    pub fn with_chunk_size(mut self, bytes: u64) -> Self {
+        self.min_segment_size = bytes;
+        self
+    }
+
+    pub fn with_random_order(mut self, random_order: bool) -> Self {
+        self.random_order = random_order;
+        self
+    }
+
+    pub fn with_retry_attempts(mut self, attempts: u32) -> Self {
+        self.retry.max_retries = attempts;
+        self
+    }
+
+    /// Delay before the first retry; later retries back off from here.
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry.initial_delay = delay.as_secs();
+        self.retry.max_delay = self.retry.max_delay.max(self.retry.initial_delay);
+        self
+    }
+
+    /// Total bandwidth cap in KB/s; `None` removes the limit.
+    pub fn with_rate_limit(mut self, rate_limit_kb: Option<u64>) -> Self {
+        self.rate_limit_kb = rate_limit_kb;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connection_timeout = timeout.as_secs();
+        self
+    }
+
+    pub fn with_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout.as_secs();
+        self
+    }
+
+    pub fn with_file_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.file_timeout = timeout.map(|t| t.as_secs());
+        self
+    }
+
+    pub fn with_batch_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.batch_timeout = timeout.map(|t| t.as_secs());
+        self
+    }
+
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    pub fn with_cache_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cache_dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_integrity_check(mut self, check: IntegrityCheck) -> Self {
+        self.integrity_check = Some(check);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: DownloadFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    pub fn with_collision_policy(mut self, policy: CollisionPolicy) -> Self {
+        self.on_collision = policy;
+        self
+    }
+
+    pub fn with_output_template(mut self, template: impl Into<String>) -> Self {
+        self.output_template = Some(template.into());
+        self
+    }
+
+    pub fn with_preflight(mut self, preflight: bool) -> Self {
+        self.preflight = preflight;
+        self
+    }
+
+    pub fn with_disk_space_check(mut self, check: bool) -> Self {
+        self.check_disk_space = check;
+        self
+    }
+
+    pub fn with_preallocation(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.insert(name.into(), value.into());
+        self
+    }
+
+    /// Adds a header sent only to URLs under `prefix`, see [`url_prefix_matches`].
+    pub fn with_url_header(
+        mut self,
+        prefix: impl Into<String>,
+        name: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Self {
+        self.url_headers
+            .entry(prefix.into())
+            .or_default()
+            .insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_auth(mut self, auth: AuthConfig) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    pub fn with_netrc(mut self, netrc_file: Option<PathBuf>) -> Self {
+        self.netrc = true;
+        self.netrc_file = netrc_file;
+        self
+    }
+
+    pub fn with_cookie_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cookie_file = Some(path.into());
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Checks everything except `urls`, for runs whose URLs come from an input file.
+    pub fn validate_settings(&self) -> Result<(), ConfigError> {
         // Validate download directory
-        if !self.download_dir.to_str().map_or(false, |s| !s.is_empty()) {
+        if self.download_dir.to_str().is_none_or(|s| s.is_empty()) {
             return Err(ConfigError::InvalidDownloadDir(
                 self.download_dir.to_string_lossy().to_string(),
             ));
@@ -106,18 +721,137 @@ impl Config {
             return Err(ConfigError::InvalidWorkers(self.workers));
         }
 
+        // Validate per-file segment count
+        if self.segments == 0 || self.segments > 32 {
+            return Err(ConfigError::InvalidSegments(self.segments));
+        }
+
+        // Validate the per-host connection limit
+        if let Some(limit) = self.max_connections_per_host {
+            if limit == 0 || limit > 100 {
+                return Err(ConfigError::InvalidConnections(limit));
+            }
+        }
+
+        // Validate retry backoff
+        if self.retry.backoff_factor.is_nan() || self.retry.backoff_factor < 1.0 {
+            return Err(ConfigError::InvalidRetry(format!(
+                "backoff_factor must be at least 1.0, got {}",
+                self.retry.backoff_factor
+            )));
+        }
+        if self.retry.initial_delay > self.retry.max_delay {
+            return Err(ConfigError::InvalidRetry(format!(
+                "initial_delay ({}) exceeds max_delay ({})",
+                self.retry.initial_delay, self.retry.max_delay
+            )));
+        }
+
+        // Validate overall deadlines
+        for (name, limit) in [
+            ("file_timeout", self.file_timeout),
+            ("batch_timeout", self.batch_timeout),
+        ] {
+            if limit == Some(0) {
+                return Err(ConfigError::InvalidTimeout(format!(
+                    "{} must be at least 1 second",
+                    name
+                )));
+            }
+        }
+
+        // Validate filter patterns and size bounds
+        if let Some(filter) = &self.filter {
+            for pattern in filter.include_patterns.iter().chain(&filter.exclude_patterns) {
+                if let Err(e) = glob::Pattern::new(pattern) {
+                    return Err(ConfigError::InvalidFilter(format!(
+                        "invalid pattern {:?}: {}",
+                        pattern, e
+                    )));
+                }
+            }
+            if let (Some(min), Some(max)) = (filter.min_size, filter.max_size) {
+                if min > max {
+                    return Err(ConfigError::InvalidFilter(format!(
+                        "min_size ({}) exceeds max_size ({})",
+                        min, max
+                    )));
+                }
+            }
+        }
+
+        // Validate the output template
+        if let Some(template) = &self.output_template {
+            OutputTemplate::parse(template).map_err(ConfigError::InvalidTemplate)?;
+        }
+
+        // Validate request headers and credentials
+        for (name, value) in self
+            .headers
+            .iter()
+            .chain(self.url_headers.values().flatten())
+        {
+            validate_header(name, value)?;
+        }
+        for prefix in self.url_headers.keys() {
+            url::Url::parse(prefix).map_err(|e| {
+                ConfigError::InvalidHeader(format!("invalid URL prefix {:?}: {}", prefix, e))
+            })?;
+        }
+        if let Some(auth) = &self.auth {
+            if auth.bearer_token.is_none() && auth.username.is_none() {
+                return Err(ConfigError::InvalidAuth(
+                    "either bearer_token or username is required".to_string(),
+                ));
+            }
+            if auth.hosts.is_empty() {
+                return Err(ConfigError::InvalidAuth(
+                    "hosts is required so credentials only go to the hosts they belong to"
+                        .to_string(),
+                ));
+            }
+        }
+
+        // Validate proxies
+        if let Some(proxy) = &self.proxy {
+            for url in proxy.url.iter().chain(proxy.overrides.values()) {
+                validate_proxy(url)?;
+            }
+            for prefix in proxy.overrides.keys() {
+                url::Url::parse(prefix).map_err(|e| {
+                    ConfigError::InvalidProxy(format!("invalid URL prefix {:?}: {}", prefix, e))
+                })?;
+            }
+            if proxy.password.is_some() && proxy.username.is_none() {
+                return Err(ConfigError::InvalidProxy(
+                    "password given without username".to_string(),
+                ));
+            }
+        }
+
+        // Validate TLS settings
+        if let Some(tls) = &self.tls {
+            if let Some(version) = &tls.min_version {
+                validate_tls_version(version)?;
+            }
+            if tls.client_key.is_some() && tls.client_cert.is_none() {
+                return Err(ConfigError::InvalidTls(
+                    "client_key given without client_cert".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the settings and that `urls` is a non-empty list of HTTP(S) URLs.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        self.validate_settings()?;
+
         // Validate URLs
         if self.urls.is_empty() {
             return Err(ConfigError::NoUrls);
         }
-        
-        // Validate URL count matches expected file indices
-        if self.urls.len() > 163 {
-            return Err(ConfigError::InvalidUrl(format!(
-                "Too many URLs (max 163), got {}",
-                self.urls.len()
-            )));
-        }
 
         for (index, url) in self.urls.iter().enumerate() {
             if !url.starts_with("http://") && !url.starts_with("https://") {
@@ -131,10 +865,20 @@ impl Config {
         Ok(())
     }
 
+    pub fn quarantine_dir(&self) -> PathBuf {
+        self.cache_dir().join("quarantine")
+    }
+
+    pub fn cache_dir(&self) -> PathBuf {
+        self.cache_dir
+            .clone()
+            .unwrap_or_else(|| self.download_dir.join(".multhreadown"))
+    }
+
+    /// Reads a TOML file on top of the defaults; keys missing from the file keep their
+    /// default values.
     pub fn from_file(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
-        Ok(config)
+        Ok(ConfigLayers::new().file(path)?.build()?)
     }
 }
 
@@ -145,3 +889,255 @@ impl FromStr for Config {
         toml::from_str(s)
     }
 }
+
+/// Prefix of the environment variables read by [`ConfigLayers::env`].
+pub const ENV_PREFIX: &str = "MULTHREADOWN_";
+
+/// Builds the effective [`Config`] from layered sources; later layers override earlier ones.
+///
+/// Starts from [`Config::default`]. Files may set any subset of the keys, and nested tables
+/// such as `[retry]` are merged key by key rather than replaced.
+///
+/// ```no_run
+/// # fn example() -> Result<(), multhreadown::config::ConfigError> {
+/// use multhreadown::config::{system_config_path, user_config_path, ConfigLayers};
+///
+/// let config = ConfigLayers::new()
+///     .optional_file(system_config_path())?
+///     .optional_file(user_config_path())?
+///     .env(std::env::vars())?
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ConfigLayers {
+    merged: toml::Table,
+}
+
+impl ConfigLayers {
+    pub fn new() -> Self {
+        let merged = match toml::Value::try_from(Config::default()) {
+            Ok(toml::Value::Table(table)) => table,
+            _ => toml::Table::new(),
+        };
+        Self { merged }
+    }
+
+    /// Merges a TOML file that must exist.
+    pub fn file(self, path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::InvalidFile(path.to_path_buf(), e.to_string()))?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|e| ConfigError::InvalidFile(path.to_path_buf(), e.to_string()))?;
+        Ok(self.overrides(table))
+    }
+
+    /// Merges a TOML file if it exists.
+    pub fn optional_file(self, path: Option<impl AsRef<Path>>) -> Result<Self, ConfigError> {
+        match path {
+            Some(path) if path.as_ref().exists() => self.file(path),
+            _ => Ok(self),
+        }
+    }
+
+    /// Merges `MULTHREADOWN_*` variables: `MULTHREADOWN_WORKERS=8` sets `workers`, and a
+    /// double underscore descends into a table, as in `MULTHREADOWN_RETRY__MAX_RETRIES=5`.
+    /// Lists such as `urls` are comma separated. `MULTHREADOWN_CONFIG` names a config file
+    /// and is left to the caller.
+    pub fn env<I>(mut self, vars: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut vars: Vec<_> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX) && name != "MULTHREADOWN_CONFIG")
+            .collect();
+        vars.sort();
+
+        for (name, raw) in vars {
+            let path: Vec<String> = name[ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            let current = lookup(&self.merged, &path);
+            let value = env_value(current, &path.join("."), &raw)
+                .map_err(|reason| ConfigError::InvalidEnv(name, reason))?;
+            self.merged = merge(self.merged, nest(&path, value));
+        }
+        Ok(self)
+    }
+
+    /// Merges explicit values, e.g. command-line flags.
+    pub fn overrides(mut self, table: toml::Table) -> Self {
+        self.merged = merge(self.merged, table);
+        self
+    }
+
+    pub fn build(self) -> Result<Config, ConfigError> {
+        toml::Value::Table(self.merged)
+            .try_into()
+            .map_err(|e: toml::de::Error| ConfigError::Invalid(e.to_string()))
+    }
+}
+
+impl Default for ConfigLayers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// System-wide config file, read before the user's.
+pub fn system_config_path() -> Option<PathBuf> {
+    cfg!(unix).then(|| PathBuf::from("/etc/multhreadown/config.toml"))
+}
+
+/// `$XDG_CONFIG_HOME/multhreadown/config.toml`, falling back to `~/.config`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("multhreadown").join("config.toml"))
+}
+
+fn merge(mut base: toml::Table, overlay: toml::Table) -> toml::Table {
+    for (key, value) in overlay {
+        let merged = match (base.remove(&key), value) {
+            (Some(toml::Value::Table(old)), toml::Value::Table(new)) => {
+                toml::Value::Table(merge(old, new))
+            }
+            (_, value) => value,
+        };
+        base.insert(key, merged);
+    }
+    base
+}
+
+fn lookup<'a>(table: &'a toml::Table, path: &[String]) -> Option<&'a toml::Value> {
+    let (last, parents) = path.split_last()?;
+    let mut table = table;
+    for key in parents {
+        table = table.get(key)?.as_table()?;
+    }
+    table.get(last)
+}
+
+fn nest(path: &[String], value: toml::Value) -> toml::Table {
+    let mut table = toml::Table::new();
+    match path {
+        [] => {}
+        [key] => {
+            table.insert(key.clone(), value);
+        }
+        [key, rest @ ..] => {
+            table.insert(key.clone(), toml::Value::Table(nest(rest, value)));
+        }
+    }
+    table
+}
+
+/// Whether `url` falls under `prefix`: the same scheme, host and port, and a path that
+/// continues the prefix's path at a segment boundary. `https://a.example.com/files` covers
+/// `https://a.example.com/files/x` but neither `https://a.example.com/files2` nor
+/// `https://a.example.com.evil.net/files`. Prefixes that are not URLs match nothing.
+pub fn url_prefix_matches(prefix: &str, url: &str) -> bool {
+    let (Ok(prefix), Ok(url)) = (url::Url::parse(prefix), url::Url::parse(url)) else {
+        return false;
+    };
+    if prefix.scheme() != url.scheme()
+        || prefix.host_str() != url.host_str()
+        || prefix.port_or_known_default() != url.port_or_known_default()
+    {
+        return false;
+    }
+    let base = prefix.path().trim_end_matches('/');
+    url.path()
+        .strip_prefix(base)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Checks that `name: value` can be sent as an HTTP header.
+pub fn validate_header(name: &str, value: &str) -> Result<(), ConfigError> {
+    reqwest::header::HeaderName::from_bytes(name.as_bytes())
+        .map_err(|_| ConfigError::InvalidHeader(format!("invalid header name {:?}", name)))?;
+    reqwest::header::HeaderValue::from_str(value).map_err(|_| {
+        ConfigError::InvalidHeader(format!("invalid value for header {}", name))
+    })?;
+    Ok(())
+}
+
+/// Optional settings that are not strings. An unset setting has no current value to take
+/// its type from, so any setting not listed here is read as a string.
+const ENV_INTEGERS: &[&str] = &[
+    "rate_limit_kb",
+    "file_timeout",
+    "batch_timeout",
+    "max_connections_per_host",
+    "filter.min_size",
+    "filter.max_size",
+];
+const ENV_BOOLEANS: &[&str] = &["integrity_check.enabled", "tls.danger_accept_invalid_certs"];
+const ENV_LISTS: &[&str] = &[
+    "auth.hosts",
+    "proxy.no_proxy",
+    "tls.ca_files",
+    "filter.include_patterns",
+    "filter.exclude_patterns",
+];
+
+/// Placeholder of the right type for a setting that is not set yet.
+fn unset_value(path: &str) -> toml::Value {
+    if ENV_INTEGERS.contains(&path) {
+        toml::Value::Integer(0)
+    } else if ENV_BOOLEANS.contains(&path) {
+        toml::Value::Boolean(false)
+    } else if ENV_LISTS.contains(&path) {
+        toml::Value::Array(Vec::new())
+    } else {
+        toml::Value::String(String::new())
+    }
+}
+
+/// Parses an environment value with the type of the setting it replaces.
+fn env_value(current: Option<&toml::Value>, path: &str, raw: &str) -> Result<toml::Value, String> {
+    let raw = raw.trim();
+    let unset;
+    let current = match current {
+        Some(value) => value,
+        None => {
+            unset = unset_value(path);
+            &unset
+        }
+    };
+    let value = match current {
+        toml::Value::String(_) => toml::Value::String(raw.to_string()),
+        toml::Value::Integer(_) => raw
+            .parse()
+            .map(toml::Value::Integer)
+            .map_err(|_| format!("expected an integer, got {:?}", raw))?,
+        toml::Value::Float(_) => raw
+            .parse()
+            .map(toml::Value::Float)
+            .map_err(|_| format!("expected a number, got {:?}", raw))?,
+        toml::Value::Boolean(_) => match raw.to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => toml::Value::Boolean(true),
+            "0" | "false" | "no" | "off" => toml::Value::Boolean(false),
+            _ => return Err(format!("expected a boolean, got {:?}", raw)),
+        },
+        toml::Value::Array(_) if !raw.starts_with('[') => toml::Value::Array(
+            raw.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| toml::Value::String(item.to_string()))
+                .collect(),
+        ),
+        // Explicit TOML for lists and tables, e.g. `["a", "b"]`
+        _ => toml::from_str::<toml::Table>(&format!("value = {}", raw))
+            .ok()
+            .and_then(|mut table| table.remove("value"))
+            .unwrap_or_else(|| toml::Value::String(raw.to_string())),
+    };
+    Ok(value)
+}
@@ -0,0 +1,230 @@
+use crate::cli::{Command, DownloadStatus};
+use crate::error::DownloadError;
+use crate::limiter::RateLimiter;
+use crate::stats::DownloadStats;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, watch};
+
+/// 运行中下载的控制句柄，可整体或按 URL 序号暂停、恢复和取消
+///
+/// 句柄可以克隆，所有副本控制同一批下载。暂停的任务会断开连接但保留 `.part` 文件和已写入的进度，
+/// 恢复后用 Range 请求从中断处继续。取消和按序号的暂停作用于正在进行的运行，没有运行时作用于下一次
+/// 运行；所有运行结束后清除，整体暂停则保留到调用 [`resume`](Self::resume)。同时进行的多次运行共享
+/// 同一组序号。状态变化会通过 [`set_status_sender`](Self::set_status_sender)
+/// 注册的通道以 [`DownloadStatus`] 发回。
+#[derive(Clone)]
+pub struct DownloadControl {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    state: watch::Sender<ControlState>,
+    limiter: Arc<RateLimiter>,
+    /// 当前（或最近一次）运行的统计
+    stats: Mutex<Arc<DownloadStats>>,
+    status_tx: Mutex<Option<mpsc::Sender<DownloadStatus>>>,
+    /// 正在进行的运行数
+    runs: Mutex<usize>,
+}
+
+/// 一次运行期间持有，释放时该次运行结束；最后一次运行结束时清除取消和按序号的暂停
+pub(crate) struct RunGuard {
+    inner: Arc<Inner>,
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let mut runs = self.inner.runs.lock().unwrap();
+        *runs -= 1;
+        if *runs == 0 {
+            self.inner.state.send_modify(|s| {
+                s.cancelled_all = false;
+                s.cancelled.clear();
+                s.paused.clear();
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ControlState {
+    paused_all: bool,
+    cancelled_all: bool,
+    paused: HashSet<usize>,
+    cancelled: HashSet<usize>,
+}
+
+impl ControlState {
+    fn is_paused(&self, index: usize) -> bool {
+        self.paused_all || self.paused.contains(&index)
+    }
+
+    fn is_cancelled(&self, index: usize) -> bool {
+        self.cancelled_all || self.cancelled.contains(&index)
+    }
+}
+
+impl DownloadControl {
+    /// `rate_limit_kb` 为 `None` 或 0 时不限速
+    pub fn new(rate_limit_kb: Option<u64>) -> Self {
+        Self::with_limiter(Arc::new(RateLimiter::new(rate_limit_kb)))
+    }
+
+    /// 使用调用方持有的限速器
+    pub fn with_limiter(limiter: Arc<RateLimiter>) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                state: watch::Sender::new(ControlState::default()),
+                limiter,
+                stats: Mutex::new(Arc::new(DownloadStats::default())),
+                status_tx: Mutex::new(None),
+                runs: Mutex::new(0),
+            }),
+        }
+    }
+
+    pub fn limiter(&self) -> &Arc<RateLimiter> {
+        &self.inner.limiter
+    }
+
+    /// 当前正在进行或最近一次完成的下载的统计
+    pub fn stats(&self) -> Arc<DownloadStats> {
+        self.inner.stats.lock().unwrap().clone()
+    }
+
+    /// 开始一次运行，返回的 [`RunGuard`] 在运行结束时释放
+    pub(crate) fn begin_run(&self) -> RunGuard {
+        *self.inner.runs.lock().unwrap() += 1;
+        RunGuard {
+            inner: self.inner.clone(),
+        }
+    }
+
+    /// 每次运行开始时换上该次运行的统计
+    pub(crate) fn set_stats(&self, stats: Arc<DownloadStats>) {
+        *self.inner.stats.lock().unwrap() = stats;
+    }
+
+    /// 状态变化发送到 `status_tx`，例如 [`InteractiveMode::new`](crate::cli::InteractiveMode::new)
+    /// 返回的发送端
+    pub fn set_status_sender(&self, status_tx: mpsc::Sender<DownloadStatus>) {
+        *self.inner.status_tx.lock().unwrap() = Some(status_tx);
+    }
+
+    pub fn pause(&self) {
+        self.inner.state.send_modify(|s| s.paused_all = true);
+        self.notify(DownloadStatus::Paused);
+    }
+
+    /// 恢复所有下载，包括单独暂停的 URL
+    pub fn resume(&self) {
+        self.inner.state.send_modify(|s| {
+            s.paused_all = false;
+            s.paused.clear();
+        });
+        self.notify(DownloadStatus::Running);
+    }
+
+    pub fn cancel(&self) {
+        self.inner.state.send_modify(|s| s.cancelled_all = true);
+        self.notify(DownloadStatus::Cancelled);
+    }
+
+    /// 暂停本批中第 `index` 个 URL（从 0 开始，按输入顺序）
+    pub fn pause_file(&self, index: usize) {
+        self.inner.state.send_modify(|s| {
+            s.paused.insert(index);
+        });
+        self.notify(DownloadStatus::FilePaused(index));
+    }
+
+    /// 恢复单独暂停的 URL；整体暂停时仍需调用 [`resume`](Self::resume)
+    pub fn resume_file(&self, index: usize) {
+        self.inner.state.send_modify(|s| {
+            s.paused.remove(&index);
+        });
+        self.notify(DownloadStatus::FileResumed(index));
+    }
+
+    pub fn cancel_file(&self, index: usize) {
+        self.inner.state.send_modify(|s| {
+            s.cancelled.insert(index);
+        });
+        self.notify(DownloadStatus::FileCancelled(index));
+    }
+
+    pub fn is_paused(&self, index: usize) -> bool {
+        self.inner.state.borrow().is_paused(index)
+    }
+
+    pub fn is_cancelled(&self, index: usize) -> bool {
+        self.inner.state.borrow().is_cancelled(index)
+    }
+
+    /// 执行交互模式发来的命令
+    pub fn apply(&self, command: Command) {
+        match command {
+            Command::Pause => self.pause(),
+            Command::Resume => self.resume(),
+            Command::Cancel => self.cancel(),
+            Command::PauseFile(index) => self.pause_file(index),
+            Command::ResumeFile(index) => self.resume_file(index),
+            Command::CancelFile(index) => self.cancel_file(index),
+            Command::ShowProgress => self.notify(DownloadStatus::Progress(self.stats().summary())),
+            Command::SetRateLimit(kb) => {
+                self.inner.limiter.set_rate_limit(Some(kb));
+                self.notify(DownloadStatus::RateLimit(
+                    self.inner.limiter.rate_limit_kb(),
+                ));
+            }
+        }
+    }
+
+    /// 下载任务在数据块之间调用：暂停时返回 [`DownloadError::Paused`]，
+    /// 调用方应断开连接后用 [`wait_until_resumed`](Self::wait_until_resumed) 等待
+    pub(crate) fn check(&self, index: usize) -> Result<(), DownloadError> {
+        let state = self.inner.state.borrow();
+        if state.is_cancelled(index) {
+            Err(DownloadError::Cancelled(index as u32))
+        } else if state.is_paused(index) {
+            Err(DownloadError::Paused(index as u32))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 开始下载前调用：暂停时等待恢复，已取消时返回 [`DownloadError::Cancelled`]
+    pub(crate) async fn pause_point(&self, index: usize) -> Result<(), DownloadError> {
+        match self.check(index) {
+            Err(DownloadError::Paused(_)) => self.wait_until_resumed(index).await,
+            result => result,
+        }
+    }
+
+    /// 等待第 `index` 个 URL 恢复；期间被取消时返回 [`DownloadError::Cancelled`]
+    pub(crate) async fn wait_until_resumed(&self, index: usize) -> Result<(), DownloadError> {
+        let mut rx = self.inner.state.subscribe();
+        let state = rx
+            .wait_for(|s| s.is_cancelled(index) || !s.is_paused(index))
+            .await
+            .map_err(|e| DownloadError::Other(e.to_string()))?;
+        if state.is_cancelled(index) {
+            return Err(DownloadError::Cancelled(index as u32));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn notify(&self, status: DownloadStatus) {
+        if let Some(tx) = self.inner.status_tx.lock().unwrap().as_ref() {
+            // 交互界面处理不过来时丢弃状态，不阻塞下载
+            let _ = tx.try_send(status);
+        }
+    }
+}
+
+impl Default for DownloadControl {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
@@ -0,0 +1,116 @@
+//! Netscape 格式的 cookie 文件（curl 的 `-b/-c`、wget 的 `--load-cookies` 和浏览器扩展导出的格式）
+//!
+//! 每行七个以制表符分隔的字段，依次为域名、是否包含子域名、路径、是否仅限 HTTPS、
+//! 过期时间（Unix 秒，0 表示会话 cookie）、名称和值。
+//! `#HttpOnly_` 开头的行是带 `HttpOnly` 标记的 cookie，其他 `#` 开头的行是注释。
+
+use reqwest::cookie::Jar;
+use reqwest::Url;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 读取 cookie 文件，已过期和无法解析的行会被跳过
+pub fn load_jar(path: &Path) -> io::Result<Jar> {
+    let text = std::fs::read_to_string(path)?;
+    let jar = Jar::default();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let mut loaded = 0;
+    for (number, line) in text.lines().enumerate() {
+        let Some(cookie) = parse_line(line) else {
+            continue;
+        };
+        let Ok(cookie) = cookie else {
+            log::warn!("{}:{}: malformed cookie line", path.display(), number + 1);
+            continue;
+        };
+        if cookie.expires != 0 && cookie.expires <= now {
+            continue;
+        }
+        let Ok(url) = Url::parse(&cookie.url()) else {
+            log::warn!("{}:{}: invalid cookie domain", path.display(), number + 1);
+            continue;
+        };
+        jar.add_cookie_str(&cookie.set_cookie(now), &url);
+        loaded += 1;
+    }
+    log::debug!("Loaded {} cookies from {}", loaded, path.display());
+    Ok(jar)
+}
+
+struct Cookie<'a> {
+    domain: &'a str,
+    include_subdomains: bool,
+    path: &'a str,
+    secure: bool,
+    http_only: bool,
+    expires: u64,
+    name: &'a str,
+    value: &'a str,
+}
+
+impl Cookie<'_> {
+    /// 设置该 cookie 的响应所对应的 URL
+    fn url(&self) -> String {
+        let scheme = if self.secure { "https" } else { "http" };
+        format!(
+            "{}://{}{}",
+            scheme,
+            self.domain.trim_start_matches('.'),
+            self.path
+        )
+    }
+
+    /// 等价的 `Set-Cookie` 头；不包含子域名的 cookie 不带 `Domain`，只发给该主机
+    fn set_cookie(&self, now: u64) -> String {
+        let mut header = format!("{}={}; Path={}", self.name, self.value, self.path);
+        if self.include_subdomains {
+            header.push_str(&format!("; Domain={}", self.domain.trim_start_matches('.')));
+        }
+        if self.secure {
+            header.push_str("; Secure");
+        }
+        if self.http_only {
+            header.push_str("; HttpOnly");
+        }
+        if self.expires != 0 {
+            header.push_str(&format!("; Max-Age={}", self.expires - now));
+        }
+        header
+    }
+}
+
+/// 注释和空行返回 `None`
+fn parse_line(line: &str) -> Option<Result<Cookie<'_>, ()>> {
+    let line = line.trim_end_matches(['\r', '\n']);
+    let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+        Some(rest) => (rest, true),
+        None => (line, false),
+    };
+    if line.trim().is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let fields: Vec<&str> = line.split('\t').collect();
+    let [domain, include_subdomains, path, secure, expires, name, value] = fields[..] else {
+        return Some(Err(()));
+    };
+    let flag = |field: &str| field.eq_ignore_ascii_case("TRUE");
+    let Ok(expires) = expires.trim().parse() else {
+        return Some(Err(()));
+    };
+    Some(Ok(Cookie {
+        domain,
+        include_subdomains: flag(include_subdomains),
+        path: if path.is_empty() { "/" } else { path },
+        secure: flag(secure),
+        http_only,
+        expires,
+        name,
+        value,
+    }))
+}
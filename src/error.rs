@@ -1,9 +1,12 @@
 use std::error::Error as StdError;
 use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 use tokio::sync::AcquireError;
 use tokio::task::JoinError;
 use thiserror::Error;
 use crate::config::ConfigError;
+use crate::hosts;
 use url;
 
 #[derive(Debug, Error)]
@@ -20,12 +23,64 @@ pub enum DownloadError {
     #[error("Network error for file {0}: {1}")]
     NetworkError(u32, String),
 
+    /// 在 `connection_timeout` 内没有建立连接
+    #[error("Connection timed out for file {0}")]
+    ConnectTimeout(u32),
+
+    /// 超过 `read_timeout` 没有收到任何数据，会从中断处重试
+    #[error("No data received for file {0} in {}s", .1.as_secs())]
+    ReadTimeout(u32, Duration),
+
+    /// 超过 `file_timeout`，重试也计入其中
+    #[error("Download of file {0} exceeded the {}s file time limit", .1.as_secs())]
+    FileTimeout(u32, Duration),
+
+    /// 超过 `batch_timeout`，本批尚未完成的下载都会失败
+    #[error("Batch time limit of {}s exceeded", .0.as_secs())]
+    BatchTimeout(Duration),
+
     #[error("Checksum mismatch for file {0}: expected {1}, got {2}")]
     ChecksumMismatch(u32, String, String),
 
+    #[error("Download of file {0} was cancelled")]
+    Cancelled(u32),
+
+    /// 下载被暂停，任务内部处理后等待恢复，不会出现在报告中
+    #[error("Download of file {0} was paused")]
+    Paused(u32),
+
+    /// 分段下载途中远程文件发生了变化，已写入的数据作废，重新探测后从头开始
+    #[error("Remote file changed during download of file {0}")]
+    RemoteChanged(u32),
+
+    /// 目标文件已被其他 URL 占用，且 `on_collision` 为 `error`
+    #[error("Target file already exists: {0}")]
+    FileExists(PathBuf),
+
+    /// 保存目录所在文件系统的剩余空间不足以容纳待下载的数据
+    #[error(
+        "Not enough disk space in {}: need {}, {} available",
+        path.display(),
+        bytesize::to_string(*needed, true),
+        bytesize::to_string(*available, true)
+    )]
+    InsufficientSpace {
+        path: PathBuf,
+        needed: u64,
+        available: u64,
+    },
+
+    /// 输出路径模板展开后会跳出保存目录或为空
+    #[error("Unsafe output path: {0}")]
+    UnsafePath(String),
+
     #[error("Invalid URL: {0}")]
     InvalidUrl(String),
 
+    /// 输入文件中无法解析的条目
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("URL parse error: {0}")]
     UrlParseError(#[from] url::ParseError),
 
@@ -42,6 +97,21 @@ pub enum DownloadError {
     AcquireError(#[from] AcquireError),
 }
 
+impl DownloadError {
+    /// 是否值得重新发起请求；本地 IO 或校验类错误重试也无济于事，
+    /// HTTP 错误按[状态码](hosts::is_retryable_status)区分
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DownloadError::Reqwest(_)
+            | DownloadError::NetworkError(..)
+            | DownloadError::ConnectTimeout(_)
+            | DownloadError::ReadTimeout(..) => true,
+            DownloadError::HttpError(_, status, _) => hosts::is_retryable_status(*status),
+            _ => false,
+        }
+    }
+}
+
 impl From<ConfigError> for DownloadError {
     fn from(err: ConfigError) -> Self {
         DownloadError::ConfigError(err.to_string())
@@ -0,0 +1,143 @@
+//! 本地文件名的推断：依次尝试 `Content-Disposition`、重定向后的 URL 和原始 URL 的最后一段，
+//! 结果都经过 [`sanitize`] 清理，不会包含路径分隔符或在常见文件系统上无效的字符
+
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+
+/// 大多数文件系统允许的最大文件名字节数
+const MAX_NAME_BYTES: usize = 255;
+
+/// 从 `Content-Disposition` 头取出文件名，`filename*`（RFC 5987）优先于 `filename`
+pub fn from_content_disposition(value: &str) -> Option<String> {
+    let mut plain = None;
+    let mut extended = None;
+    for (key, value) in parameters(value) {
+        if key.eq_ignore_ascii_case("filename*") {
+            extended = decode_extended(&value);
+        } else if key.eq_ignore_ascii_case("filename") {
+            plain = Some(value);
+        }
+    }
+    extended.or(plain).and_then(|name| sanitize(&name))
+}
+
+/// URL 路径的最后一个非空段，经过百分号解码；查询串和片段不参与
+pub fn from_url(url: &Url) -> Option<String> {
+    let segment = url.path_segments()?.rev().find(|s| !s.is_empty())?;
+    sanitize(&percent_decode_str(segment).decode_utf8_lossy())
+}
+
+/// 清理远程给出的文件名：只保留最后一个路径分量，替换控制字符和 Windows 不允许的字符，
+/// 去掉首尾空白和末尾的点，并限制长度。清理后为空或是 `.`、`..` 时返回 `None`
+pub fn sanitize(name: &str) -> Option<String> {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let cleaned = cleaned.trim().trim_end_matches('.');
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        return None;
+    }
+    Some(truncate(cleaned))
+}
+
+/// 重名时使用的候选名：`name.ext` 的第 `n` 个候选为 `name (n).ext`
+pub fn numbered(name: &str, n: usize) -> String {
+    let (stem, ext) = split_extension(name);
+    truncate(&format!("{} ({}){}", stem, n, ext))
+}
+
+/// 拆出扩展名（含点）；以点开头的隐藏文件名整体视为主名
+fn split_extension(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(dot),
+        _ => (name, ""),
+    }
+}
+
+/// 超长时截短主名，保留扩展名
+fn truncate(name: &str) -> String {
+    if name.len() <= MAX_NAME_BYTES {
+        return name.to_string();
+    }
+    let (stem, ext) = split_extension(name);
+    let ext = if ext.len() < MAX_NAME_BYTES / 2 {
+        ext
+    } else {
+        ""
+    };
+    let mut end = MAX_NAME_BYTES - ext.len();
+    while !stem.is_char_boundary(end.min(stem.len())) {
+        end -= 1;
+    }
+    format!("{}{}", &stem[..end.min(stem.len())], ext)
+}
+
+/// 解析 `attachment; filename="a;b.txt"; filename*=UTF-8''a%3Bb.txt` 中的参数，
+/// 引号内的分号和反斜杠转义按 RFC 6266 处理
+fn parameters(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = value;
+    loop {
+        rest = rest.trim_start();
+        // 没有 `=` 的部分（例如开头的 `attachment`）直接跳过
+        let eq = match (rest.find('='), rest.find(';')) {
+            (Some(eq), semi) if semi.is_none_or(|semi| eq < semi) => eq,
+            (_, Some(semi)) => {
+                rest = &rest[semi + 1..];
+                continue;
+            }
+            _ => break,
+        };
+        let key = rest[..eq].trim().to_string();
+        rest = rest[eq + 1..].trim_start();
+
+        let mut parsed = String::new();
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let mut chars = quoted.char_indices();
+            let mut end = quoted.len();
+            while let Some((i, c)) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some((_, escaped)) = chars.next() {
+                            parsed.push(escaped);
+                        }
+                    }
+                    '"' => {
+                        end = i + 1;
+                        break;
+                    }
+                    c => parsed.push(c),
+                }
+            }
+            rest = &quoted[end..];
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            parsed.push_str(rest[..end].trim());
+            rest = &rest[end..];
+        }
+        params.push((key, parsed));
+    }
+    params
+}
+
+/// 解码 `charset'language'percent-encoded`，支持 UTF-8 和 ISO-8859-1
+fn decode_extended(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = parts.next()?;
+    let _language = parts.next()?;
+    let encoded = parts.next()?;
+    let bytes: Vec<u8> = percent_decode_str(encoded).collect();
+    if charset.eq_ignore_ascii_case("utf-8") {
+        String::from_utf8(bytes).ok()
+    } else if charset.eq_ignore_ascii_case("iso-8859-1") {
+        Some(bytes.into_iter().map(char::from).collect())
+    } else {
+        None
+    }
+}
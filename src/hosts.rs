@@ -0,0 +1,160 @@
+//! 按主机共享的状态：退避和连接数上限
+//!
+//! 主机以 `主机名:端口` 区分，见 [`host_key`]。
+//!
+//! 服务器返回 429 或 503 时，整个主机进入退避：所有访问该主机的下载任务在发出下一个请求前
+//! 都要等到退避结束，而不只是收到该响应的任务。退避时长优先使用响应的 `Retry-After`，
+//! 没有时按重试策略随该主机连续被限流的次数增长；收到成功的响应后计数清零。
+//!
+//! 配置了 `max_connections_per_host` 时，每个请求（包括分段和探测）在发出前都要取得该主机的
+//! 一个许可，直到响应读完才归还。
+
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{AcquireError, OwnedSemaphorePermit, Semaphore};
+
+/// URL 所属的主机，`主机名:端口`；无法解析的 URL 归入空字符串
+pub fn host_key(url: &str) -> String {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return String::new();
+    };
+    match (url.host_str(), url.port_or_known_default()) {
+        (Some(host), Some(port)) => format!("{}:{}", host, port),
+        (Some(host), None) => host.to_string(),
+        _ => String::new(),
+    }
+}
+
+/// 每个主机同时进行的请求数上限
+#[derive(Debug, Default)]
+pub struct HostLimits {
+    limit: Option<usize>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimits {
+    /// `limit` 为 `None` 时不限制
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// 等待该主机的一个许可；不限制时立即返回 `None`
+    pub async fn acquire(&self, host: &str) -> Result<Option<OwnedSemaphorePermit>, AcquireError> {
+        let Some(limit) = self.limit else {
+            return Ok(None);
+        };
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(limit)))
+            .clone();
+        semaphore.acquire_owned().await.map(Some)
+    }
+}
+
+/// 各主机的退避状态
+#[derive(Debug, Default)]
+pub struct HostBackoff {
+    hosts: Mutex<HashMap<String, Throttle>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Throttle {
+    until: Instant,
+    /// 连续被限流的次数
+    strikes: u32,
+}
+
+impl HostBackoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次限流，返回该主机连续被限流的次数；`delay` 由调用方按次数决定，
+    /// 只会延长已有的退避，不会缩短
+    pub fn throttle(&self, host: &str, delay: impl FnOnce(u32) -> Duration) -> u32 {
+        let mut hosts = self.hosts.lock().unwrap();
+        let now = Instant::now();
+        let entry = hosts.entry(host.to_string()).or_insert(Throttle {
+            until: now,
+            strikes: 0,
+        });
+        entry.strikes += 1;
+        entry.until = entry.until.max(now + delay(entry.strikes));
+        entry.strikes
+    }
+
+    /// 该主机恢复正常，下次限流重新从第一次开始计算
+    pub fn recovered(&self, host: &str) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if hosts.get(host).is_some_and(|t| t.until <= Instant::now()) {
+            hosts.remove(host);
+        }
+    }
+
+    /// 距离退避结束还有多久，不在退避中时返回 `None`
+    pub fn remaining(&self, host: &str) -> Option<Duration> {
+        let hosts = self.hosts.lock().unwrap();
+        let remaining = hosts
+            .get(host)?
+            .until
+            .saturating_duration_since(Instant::now());
+        (!remaining.is_zero()).then_some(remaining)
+    }
+}
+
+/// 要求客户端放慢的状态码
+pub fn is_throttle(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+/// 值得重试的状态码：超时、限流和网关类错误；其余 4xx 和 5xx 重试也不会有不同的结果
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 425 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// `Retry-After` 等待时间的默认上限，见 [`capped_retry_after`]
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// [`retry_after`] 限制在 `limit` 以内：服务器要求等待更久（或给出了异常大的值）时记录日志，
+/// 只等待 `limit`
+pub fn capped_retry_after(headers: &HeaderMap, limit: Duration) -> Option<Duration> {
+    let wait = retry_after(headers)?;
+    if wait > limit {
+        log::warn!(
+            "Retry-After of {}s exceeds the limit, waiting {}s instead",
+            wait.as_secs(),
+            limit.as_secs()
+        );
+        return Some(limit);
+    }
+    Some(wait)
+}
+
+/// 解析 `Retry-After`：秒数或 HTTP 日期，已经过去的日期视为 0
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,271 @@
+//! aria2 风格的输入文件：每行一个 URL，其后以空白开头的 `key=value` 行是该 URL 的选项
+//!
+//! ```text
+//! # 注释和空行会被忽略
+//! https://example.com/debian.iso
+//!   out=images/debian-12.iso
+//!   dir=/srv/mirror
+//!   checksum=sha-256=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+//! https://example.com/README
+//! ```
+//!
+//! 支持的选项：`out`（相对于保存目录的文件名）、`dir`（保存目录，相对路径基于当前目录）、
+//! `template`（该 URL 的[输出路径模板](crate::template)）、`header`（`名称: 值`，可以出现多次）、
+//! `proxy`（代理 URL 或 `direct`，也可以写作 aria2 的 `all-proxy`）和 `checksum`（`算法=摘要`）。
+//! 其他 aria2 选项会被忽略并记录警告。
+
+use crate::checksum;
+use crate::config::{self, ChecksumAlgorithm};
+use crate::template::OutputTemplate;
+use futures_util::stream::{self, Stream};
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+/// 一个待下载的 URL 及其单独的选项
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadRequest {
+    pub url: String,
+    /// 保存的文件名，可以包含子目录；默认取 URL 最后一段
+    pub out: Option<PathBuf>,
+    /// 保存目录，默认为 [`Config::download_dir`](crate::config::Config::download_dir)
+    pub dir: Option<PathBuf>,
+    /// 输出路径模板，优先于配置中的 `output_template`；指定了 `out` 时不使用
+    pub template: Option<String>,
+    /// 期望的摘要，优先于配置中的 `integrity_check`
+    pub checksum: Option<(ChecksumAlgorithm, String)>,
+    /// 该 URL 额外的请求头，覆盖配置中的同名请求头
+    pub headers: Vec<(String, String)>,
+    /// 该 URL 使用的代理，`direct` 表示直连；优先于配置中的 `proxy`
+    pub proxy: Option<String>,
+}
+
+impl DownloadRequest {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            out: None,
+            dir: None,
+            template: None,
+            checksum: None,
+            headers: Vec::new(),
+            proxy: None,
+        }
+    }
+
+    pub fn with_out(mut self, out: impl Into<PathBuf>) -> Self {
+        self.out = Some(out.into());
+        self
+    }
+
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    pub fn with_template(mut self, template: impl Into<String>) -> Self {
+        self.template = Some(template.into());
+        self
+    }
+
+    pub fn with_checksum(mut self, algorithm: ChecksumAlgorithm, hex: impl Into<String>) -> Self {
+        self.checksum = Some((algorithm, hex.into()));
+        self
+    }
+
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+}
+
+impl From<String> for DownloadRequest {
+    fn from(url: String) -> Self {
+        Self::new(url)
+    }
+}
+
+impl From<&str> for DownloadRequest {
+    fn from(url: &str) -> Self {
+        Self::new(url)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum InputError {
+    /// 无法解析的条目；`url` 为出错选项所属的 URL
+    #[error("line {line}: {message}")]
+    Invalid {
+        line: usize,
+        url: Option<String>,
+        message: String,
+    },
+
+    #[error("failed to read input: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl InputError {
+    pub fn url(&self) -> Option<&str> {
+        match self {
+            InputError::Invalid { url, .. } => url.as_deref(),
+            InputError::Io(_) => None,
+        }
+    }
+}
+
+/// 逐行解析输入文件；一个 URL 的选项行全部读完后才产出该条目
+#[derive(Debug, Default)]
+pub struct InputParser {
+    line: usize,
+    current: Option<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    request: DownloadRequest,
+    /// 该条目第一个无效选项
+    error: Option<InputError>,
+}
+
+impl Pending {
+    fn into_result(self) -> Result<DownloadRequest, InputError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.request),
+        }
+    }
+}
+
+impl InputParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读入一行，返回因此结束的上一个条目
+    pub fn push_line(&mut self, line: &str) -> Option<Result<DownloadRequest, InputError>> {
+        self.line += 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            return None;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            let Some(current) = self.current.as_mut() else {
+                return Some(Err(InputError::Invalid {
+                    line: self.line,
+                    url: None,
+                    message: format!("option {:?} does not follow a URL", trimmed),
+                }));
+            };
+            if let Err(message) = apply_option(&mut current.request, trimmed) {
+                current.error.get_or_insert(InputError::Invalid {
+                    line: self.line,
+                    url: Some(current.request.url.clone()),
+                    message,
+                });
+            }
+            return None;
+        }
+
+        // aria2 用制表符分隔同一文件的多个镜像，这里只使用第一个
+        let mut mirrors = trimmed.split('\t').filter(|s| !s.is_empty());
+        let url = mirrors.next().unwrap_or_default().to_string();
+        if mirrors.next().is_some() {
+            log::warn!("line {}: ignoring mirror URLs after {}", self.line, url);
+        }
+        let next = Pending {
+            request: DownloadRequest::new(url),
+            error: None,
+        };
+        self.current.replace(next).map(Pending::into_result)
+    }
+
+    /// 输入结束，返回最后一个条目
+    pub fn finish(&mut self) -> Option<Result<DownloadRequest, InputError>> {
+        self.current.take().map(Pending::into_result)
+    }
+}
+
+fn apply_option(request: &mut DownloadRequest, option: &str) -> Result<(), String> {
+    let (key, value) = option
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, got {:?}", option))?;
+    let value = value.trim();
+    match key.trim() {
+        "out" => {
+            if value.is_empty() {
+                return Err("out must not be empty".to_string());
+            }
+            request.out = Some(PathBuf::from(value));
+        }
+        "dir" => {
+            if value.is_empty() {
+                return Err("dir must not be empty".to_string());
+            }
+            request.dir = Some(PathBuf::from(value));
+        }
+        "template" => {
+            OutputTemplate::parse(value)?;
+            request.template = Some(value.to_string());
+        }
+        "header" => {
+            let (name, value) = value
+                .split_once(':')
+                .ok_or_else(|| format!("expected header=<name>: <value>, got {:?}", value))?;
+            let (name, value) = (name.trim(), value.trim());
+            config::validate_header(name, value).map_err(|e| e.to_string())?;
+            request.headers.push((name.to_string(), value.to_string()));
+        }
+        // aria2 使用 `all-proxy`
+        "proxy" | "all-proxy" => {
+            config::validate_proxy(value).map_err(|e| e.to_string())?;
+            request.proxy = Some(value.to_string());
+        }
+        "checksum" => {
+            let (name, hex) = value
+                .split_once('=')
+                .ok_or_else(|| format!("expected checksum=<algorithm>=<hex>, got {:?}", value))?;
+            let algorithm = checksum::algorithm_from_name(name)
+                .ok_or_else(|| format!("unsupported checksum algorithm {:?}", name))?;
+            if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("invalid {} digest {:?}", name, hex));
+            }
+            request.checksum = Some((algorithm, hex.to_ascii_lowercase()));
+        }
+        other => log::warn!(
+            "Ignoring unsupported option {:?} for {}",
+            other,
+            request.url
+        ),
+    }
+    Ok(())
+}
+
+/// 边读边解析，不会把整个输入读入内存；读取失败时产出 [`InputError::Io`] 后结束
+pub fn read_requests<R>(reader: R) -> impl Stream<Item = Result<DownloadRequest, InputError>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let state = Some((reader.lines(), InputParser::new()));
+    stream::unfold(state, |state| async move {
+        let (mut lines, mut parser) = state?;
+        loop {
+            match lines.next_line().await {
+                Ok(Some(line)) => {
+                    if let Some(item) = parser.push_line(&line) {
+                        return Some((item, Some((lines, parser))));
+                    }
+                }
+                Ok(None) => return parser.finish().map(|item| (item, None)),
+                Err(e) => return Some((Err(e.into()), None)),
+            }
+        }
+    })
+}
@@ -4,20 +4,38 @@
 //! with support for pause, resume, and rate limiting.
 
 // Export public modules
+pub mod auth;
 pub mod cache;
+pub mod checksum;
 pub mod cli;
 pub mod config;
+pub mod control;
+pub mod cookies;
 pub mod downloader;
 pub mod error;
 pub mod events;
+pub mod filename;
+pub mod hosts;
+pub mod input;
+pub mod limiter;
+pub mod maintenance;
 pub mod progress;
+pub mod report;
 pub mod stats;
+pub mod template;
 pub mod utils;
 
 pub use cache::{CacheManager, DownloadCache};
 pub use config::Config;
-pub use downloader::download_all_files;
+pub use control::DownloadControl;
+pub use downloader::{
+    download_all_files, download_all_files_with_control, download_all_files_with_handler,
+    download_all_files_with_limiter, download_all_files_with_stats, Downloader, DownloaderBuilder,
+};
 pub use error::DownloadError;
 pub use events::{DefaultEventHandler, DownloadEventHandler};
+pub use input::DownloadRequest;
+pub use limiter::RateLimiter;
 pub use progress::GlobalProgress;
-pub use stats::DownloadStats;
+pub use report::{DownloadOutcome, DownloadReport, UrlReport};
+pub use stats::{DownloadStats, DownloadSummary};
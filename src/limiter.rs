@@ -0,0 +1,81 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// 所有下载任务共享的令牌桶限速器
+///
+/// 令牌以每秒 `rate_limit_kb * 1024` 字节的速度补充，桶容量为一秒的流量。
+/// 读取数据后按实际字节数扣减令牌，余额为负时调用方需等待欠额补齐，
+/// 因此多个 worker 的总吞吐不会超过上限。
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+struct Bucket {
+    bytes_per_sec: Option<u64>,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self) {
+        let now = Instant::now();
+        if let Some(rate) = self.bytes_per_sec {
+            let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * rate as f64).min(rate as f64);
+        }
+        self.last_refill = now;
+    }
+}
+
+impl RateLimiter {
+    /// `None` 或 `Some(0)` 表示不限速
+    pub fn new(rate_limit_kb: Option<u64>) -> Self {
+        let bytes_per_sec = to_bytes_per_sec(rate_limit_kb);
+        Self {
+            bucket: Mutex::new(Bucket {
+                bytes_per_sec,
+                tokens: bytes_per_sec.unwrap_or(0) as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// 运行时调整限速，对所有正在进行的下载立即生效
+    pub fn set_rate_limit(&self, rate_limit_kb: Option<u64>) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.refill();
+        bucket.bytes_per_sec = to_bytes_per_sec(rate_limit_kb);
+        bucket.tokens = match bucket.bytes_per_sec {
+            Some(rate) => bucket.tokens.min(rate as f64),
+            None => 0.0,
+        };
+    }
+
+    pub fn rate_limit_kb(&self) -> Option<u64> {
+        self.bucket.lock().unwrap().bytes_per_sec.map(|b| b / 1024)
+    }
+
+    /// 扣减 `bytes` 个令牌，超出当前余额时等待补充
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            bucket.refill();
+            let Some(rate) = bucket.bytes_per_sec else {
+                return;
+            };
+            bucket.tokens -= bytes as f64;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / rate as f64)
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+fn to_bytes_per_sec(rate_limit_kb: Option<u64>) -> Option<u64> {
+    rate_limit_kb
+        .filter(|&kb| kb > 0)
+        .map(|kb| kb.saturating_mul(1024))
+}
@@ -1,81 +1,595 @@
-mod config;
-mod downloader;
-mod error;
-mod progress;
-mod utils;
-
-use clap::Parser;
-use config::{Config, RetryConfig};
-use downloader::download_all_files;
-use error::DownloadError;
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use futures_util::stream::{self, BoxStream, StreamExt};
 use log::info;
-use std::path::PathBuf;
+use multhreadown::cli::InteractiveMode;
+use multhreadown::config::{system_config_path, user_config_path, Config, ConfigLayers};
+use multhreadown::input::{self, InputError};
+use multhreadown::maintenance::{self, VerifyStatus};
+use multhreadown::{DownloadControl, DownloadError, DownloadRequest, Downloader};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufRead, BufReader};
+
+type Requests = BoxStream<'static, Result<DownloadRequest, InputError>>;
 
 #[derive(Parser, Debug)]
 #[command(name = "multhreadown")]
 #[command(about = "A multi-threaded download tool", long_about = None)]
+#[command(after_help = CONFIG_HELP)]
 struct Cli {
-    /// Path to config file
-    #[arg(short, long, value_name = "FILE")]
+    /// Path to config file, read after the system and user config files
+    #[arg(short, long, value_name = "FILE", global = true, env = "MULTHREADOWN_CONFIG")]
     config: Option<PathBuf>,
 
-    /// Number of worker threads
-    #[arg(short, long, default_value_t = 4)]
-    workers: usize,
+    /// Verbose output
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long, global = true)]
+    print_config: bool,
+
+    #[command(flatten)]
+    settings: SettingArgs,
 
+    #[command(subcommand)]
+    command: Option<Commands>,
+}
+
+const CONFIG_HELP: &str = "\
+Settings are merged in this order, later sources winning:
+  built-in defaults, /etc/multhreadown/config.toml,
+  $XDG_CONFIG_HOME/multhreadown/config.toml (or ~/.config/...), --config FILE,
+  MULTHREADOWN_* environment variables (e.g. MULTHREADOWN_WORKERS=8,
+  MULTHREADOWN_RETRY__MAX_RETRIES=5), then command-line flags.";
+
+/// 覆盖配置文件和环境变量的命令行参数
+#[derive(Args, Debug)]
+struct SettingArgs {
     /// Download directory
-    #[arg(short, long, value_name = "DIR")]
-    download_dir: PathBuf,
+    #[arg(short, long, value_name = "DIR", global = true)]
+    download_dir: Option<PathBuf>,
+
+    /// Number of files downloaded at the same time
+    #[arg(short, long, global = true)]
+    workers: Option<usize>,
+
+    /// Parallel range requests per file
+    #[arg(long, global = true)]
+    segments: Option<usize>,
+
+    /// Maximum simultaneous requests to one host, counting segments
+    #[arg(long, value_name = "N", global = true)]
+    max_connections_per_host: Option<usize>,
+
+    /// Total bandwidth cap in KB/s, 0 for unlimited
+    #[arg(long, value_name = "KB", global = true)]
+    rate_limit: Option<u64>,
+
+    /// Connection timeout in seconds
+    #[arg(long, value_name = "SECS", global = true)]
+    connection_timeout: Option<u64>,
+
+    /// Seconds without data before a request is retried, 0 to wait indefinitely
+    #[arg(long, value_name = "SECS", global = true)]
+    read_timeout: Option<u64>,
+
+    /// Overall time limit per file in seconds, including retries
+    #[arg(long, value_name = "SECS", global = true)]
+    file_timeout: Option<u64>,
+
+    /// Overall time limit for the whole run in seconds
+    #[arg(long, value_name = "SECS", global = true)]
+    batch_timeout: Option<u64>,
+
+    /// Maximum retries per file
+    #[arg(long, global = true)]
+    retries: Option<u32>,
+
+    /// Delay before the first retry in seconds
+    #[arg(long, value_name = "SECS", global = true)]
+    retry_delay: Option<u64>,
+
+    /// Upper bound for the retry delay in seconds
+    #[arg(long, value_name = "SECS", global = true)]
+    max_retry_delay: Option<u64>,
+
+    /// What to do when another URL already uses the file name
+    #[arg(
+        long,
+        value_name = "POLICY",
+        global = true,
+        value_parser = ["overwrite", "skip", "rename", "error"]
+    )]
+    on_collision: Option<String>,
+
+    /// Output path template such as `{host}/{path}` or `{index:03}-{name}`
+    #[arg(long, value_name = "TEMPLATE", global = true)]
+    output_template: Option<String>,
+
+    /// Probe all URLs for their sizes before downloading
+    #[arg(long, global = true)]
+    preflight: bool,
+
+    /// Reserve disk blocks for downloads of known size up front
+    #[arg(long, global = true)]
+    preallocate: bool,
+
+    /// Start downloads even if the disk looks too full for them
+    #[arg(long, global = true)]
+    no_space_check: bool,
+
+    /// Extra request header, e.g. -H "X-Api-Key: secret"; may be repeated
+    #[arg(short = 'H', long = "header", value_name = "NAME: VALUE", global = true)]
+    headers: Vec<String>,
+
+    /// User-Agent sent with every request
+    #[arg(long, global = true)]
+    user_agent: Option<String>,
+
+    /// Netscape-format cookie file
+    #[arg(long, value_name = "FILE", global = true)]
+    cookies: Option<PathBuf>,
+
+    /// Read credentials from $NETRC or ~/.netrc
+    #[arg(long, global = true)]
+    netrc: bool,
+
+    /// Proxy URL (http, https, socks5 or socks5h)
+    #[arg(long, value_name = "URL", global = true)]
+    proxy: Option<String>,
+
+    /// Comma-separated hosts, domains or CIDR ranges reached without the proxy
+    #[arg(long, value_name = "HOSTS", value_delimiter = ',', global = true)]
+    no_proxy: Vec<String>,
+
+    /// Extra PEM file of trusted root certificates; may be repeated
+    #[arg(long = "ca-file", value_name = "FILE", global = true)]
+    ca_files: Vec<PathBuf>,
+
+    /// PEM client certificate for servers that require one
+    #[arg(long, value_name = "FILE", global = true)]
+    client_cert: Option<PathBuf>,
+
+    /// PKCS#8 PEM key for --client-cert, if not in the same file
+    #[arg(long, value_name = "FILE", global = true)]
+    client_key: Option<PathBuf>,
+
+    /// Lowest accepted TLS version (1.0, 1.1 or 1.2)
+    #[arg(long, value_name = "VERSION", global = true)]
+    tls_min_version: Option<String>,
+
+    /// Do not verify TLS certificates at all (lab environments only)
+    #[arg(long, global = true)]
+    danger_accept_invalid_certs: bool,
+}
+
+impl SettingArgs {
+    fn to_table(&self) -> Result<toml::Table, DownloadError> {
+        let mut table = toml::Table::new();
+        let mut retry = toml::Table::new();
+        let int = |v: u64| toml::Value::Integer(v as i64);
+
+        if let Some(dir) = &self.download_dir {
+            table.insert(
+                "download_dir".into(),
+                toml::Value::String(dir.to_string_lossy().into_owned()),
+            );
+        }
+        if let Some(workers) = self.workers {
+            table.insert("workers".into(), int(workers as u64));
+            table.insert("concurrent_downloads".into(), int(workers as u64));
+        }
+        if let Some(segments) = self.segments {
+            table.insert("segments".into(), int(segments as u64));
+        }
+        if let Some(limit) = self.max_connections_per_host {
+            table.insert("max_connections_per_host".into(), int(limit as u64));
+        }
+        if let Some(kb) = self.rate_limit {
+            table.insert("rate_limit_kb".into(), int(kb));
+        }
+        if let Some(secs) = self.connection_timeout {
+            table.insert("connection_timeout".into(), int(secs));
+        }
+        if let Some(secs) = self.read_timeout {
+            table.insert("read_timeout".into(), int(secs));
+        }
+        if let Some(secs) = self.file_timeout {
+            table.insert("file_timeout".into(), int(secs));
+        }
+        if let Some(secs) = self.batch_timeout {
+            table.insert("batch_timeout".into(), int(secs));
+        }
+        if let Some(retries) = self.retries {
+            retry.insert("max_retries".into(), int(retries as u64));
+        }
+        if let Some(secs) = self.retry_delay {
+            retry.insert("initial_delay".into(), int(secs));
+        }
+        if let Some(secs) = self.max_retry_delay {
+            retry.insert("max_delay".into(), int(secs));
+        }
+        if let Some(policy) = &self.on_collision {
+            table.insert("on_collision".into(), toml::Value::String(policy.clone()));
+        }
+        if let Some(template) = &self.output_template {
+            table.insert(
+                "output_template".into(),
+                toml::Value::String(template.clone()),
+            );
+        }
+        if self.preflight {
+            table.insert("preflight".into(), toml::Value::Boolean(true));
+        }
+        if self.preallocate {
+            table.insert("preallocate".into(), toml::Value::Boolean(true));
+        }
+        if self.no_space_check {
+            table.insert("check_disk_space".into(), toml::Value::Boolean(false));
+        }
+        if !self.headers.is_empty() {
+            let mut headers = toml::Table::new();
+            for header in &self.headers {
+                let (name, value) = header.split_once(':').ok_or_else(|| {
+                    DownloadError::ConfigError(format!(
+                        "expected --header \"NAME: VALUE\", got {:?}",
+                        header
+                    ))
+                })?;
+                headers.insert(
+                    name.trim().to_string(),
+                    toml::Value::String(value.trim().to_string()),
+                );
+            }
+            table.insert("headers".into(), toml::Value::Table(headers));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            table.insert(
+                "user_agent".into(),
+                toml::Value::String(user_agent.clone()),
+            );
+        }
+        if let Some(path) = &self.cookies {
+            table.insert(
+                "cookie_file".into(),
+                toml::Value::String(path.to_string_lossy().into_owned()),
+            );
+        }
+        if self.netrc {
+            table.insert("netrc".into(), toml::Value::Boolean(true));
+        }
+        let mut proxy = toml::Table::new();
+        if let Some(url) = &self.proxy {
+            proxy.insert("url".into(), toml::Value::String(url.clone()));
+        }
+        if !self.no_proxy.is_empty() {
+            let hosts = self.no_proxy.iter().cloned().map(toml::Value::String);
+            proxy.insert("no_proxy".into(), toml::Value::Array(hosts.collect()));
+        }
+        if !proxy.is_empty() {
+            table.insert("proxy".into(), toml::Value::Table(proxy));
+        }
+        let path = |p: &PathBuf| toml::Value::String(p.to_string_lossy().into_owned());
+        let mut tls = toml::Table::new();
+        if !self.ca_files.is_empty() {
+            tls.insert(
+                "ca_files".into(),
+                toml::Value::Array(self.ca_files.iter().map(path).collect()),
+            );
+        }
+        if let Some(cert) = &self.client_cert {
+            tls.insert("client_cert".into(), path(cert));
+        }
+        if let Some(key) = &self.client_key {
+            tls.insert("client_key".into(), path(key));
+        }
+        if let Some(version) = &self.tls_min_version {
+            tls.insert("min_version".into(), toml::Value::String(version.clone()));
+        }
+        if self.danger_accept_invalid_certs {
+            tls.insert(
+                "danger_accept_invalid_certs".into(),
+                toml::Value::Boolean(true),
+            );
+        }
+        if !tls.is_empty() {
+            table.insert("tls".into(), toml::Value::Table(tls));
+        }
+        if !retry.is_empty() {
+            table.insert("retry".into(), toml::Value::Table(retry));
+        }
+        Ok(table)
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Download the given URLs, or the URLs from the config file
+    #[command(visible_alias = "download")]
+    Get(GetArgs),
+    /// Continue every unfinished download recorded in the cache
+    Resume(RunArgs),
+    /// List partial downloads recorded in the cache
+    Status,
+    /// Re-check the checksums of finished downloads
+    Verify,
+    /// Remove leftover `.part` files of recorded downloads and stale cache entries
+    Clean {
+        /// Also discard unfinished downloads that could still be resumed, and remove
+        /// every `.part` file in the download directory
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+struct GetArgs {
+    /// URLs to download
+    #[arg(value_name = "URLS")]
+    urls: Vec<String>,
+
+    /// Read URLs from FILE (`-` for stdin), one per line; indented `out=`, `dir=` and
+    /// `checksum=<algo>=<hex>` lines set options for the URL above them
+    #[arg(short = 'f', long, value_name = "FILE")]
+    input_file: Option<PathBuf>,
 
     /// Enable random download order
     #[arg(short, long)]
     random_order: bool,
 
-    /// Verbose output
-    #[arg(short, long)]
-    verbose: bool,
+    #[command(flatten)]
+    run: RunArgs,
+}
 
-    /// URLs to download
-    #[arg(short = 'u', long = "urls", value_name = "URLS", num_args = 1.., required = true)]
-    urls: Vec<String>,
+#[derive(Args, Debug)]
+struct RunArgs {
+    /// Read commands from stdin while downloading: `pause [N]`, `resume [N]`,
+    /// `cancel [N]`, `progress` and `limit <KB>`
+    #[arg(short, long)]
+    interactive: bool,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), DownloadError> {
+    let cli = Cli::parse();
+
+    let level = if cli.verbose {
+        log::LevelFilter::Debug
+    } else {
+        log::LevelFilter::Info
+    };
     env_logger::Builder::from_default_env()
-        .filter_level(log::LevelFilter::Info)
+        .filter_level(level)
         .init();
 
-    let cli = Cli::parse();
+    let mut overrides = cli.settings.to_table()?;
+    if let Some(Commands::Get(args)) = &cli.command {
+        if !args.urls.is_empty() {
+            let urls = args.urls.iter().cloned().map(toml::Value::String).collect();
+            overrides.insert("urls".into(), toml::Value::Array(urls));
+        }
+        if args.random_order {
+            overrides.insert("random_order".into(), toml::Value::Boolean(true));
+        }
+    }
+
+    let mut layers = ConfigLayers::new()
+        .optional_file(system_config_path())?
+        .optional_file(user_config_path())?;
+    if let Some(path) = &cli.config {
+        layers = layers.file(path)?;
+    }
+    let mut config = layers.env(std::env::vars())?.overrides(overrides).build()?;
+    // Like `Config::with_retry_delay`, a longer --retry-delay raises the cap unless
+    // --max-retry-delay sets it; the cap from lower layers is only known after merging.
+    if let (Some(secs), None) = (cli.settings.retry_delay, cli.settings.max_retry_delay) {
+        config = config.with_retry_delay(Duration::from_secs(secs));
+    }
 
-    if cli.verbose {
-        env_logger::Builder::from_default_env()
-            .filter_level(log::LevelFilter::Debug)
-            .init();
+    if cli.print_config {
+        let content =
+            toml::to_string_pretty(&config).map_err(|e| DownloadError::Other(e.to_string()))?;
+        print!("{}", content);
+        return Ok(());
     }
 
-    let config = match cli.config {
-        Some(path) => Config::from_file(&path)?,
-        None => Config {
-            download_dir: cli.download_dir,
-            workers: cli.workers,
-            random_order: cli.random_order,
-            urls: cli.urls,
-            rate_limit_kb: None,
-            retry: RetryConfig::default(),
-            concurrent_downloads: cli.workers,
-            connection_timeout: 30,
-        },
+    let Some(command) = cli.command else {
+        Cli::command()
+            .error(ErrorKind::MissingSubcommand, "a subcommand is required")
+            .exit();
     };
 
-    config.validate()?;
-    
+    let success = match command {
+        Commands::Get(args) => {
+            let requests = match args.input_file {
+                None => {
+                    config.validate()?;
+                    url_requests(&config)
+                }
+                Some(path) => {
+                    config.validate_settings()?;
+                    if args.run.interactive && path == Path::new("-") {
+                        return Err(DownloadError::ConfigError(
+                            "--interactive reads commands from stdin and cannot be combined with --input-file -"
+                                .to_string(),
+                        ));
+                    }
+                    // 命令行和配置中的 URL 排在输入文件之前
+                    let input = input::read_requests(open_input(&path).await?);
+                    url_requests(&config).chain(input).boxed()
+                }
+            };
+            download(config, args.run, requests).await?
+        }
+        Commands::Resume(args) => {
+            config.validate_settings()?;
+            let partials = maintenance::partial_downloads(&config).await?;
+            if partials.is_empty() {
+                println!("Nothing to resume");
+                return Ok(());
+            }
+            // 按记录的路径续传，输入文件中的 `out` 和 `dir` 选项因此仍然有效
+            let requests: Vec<_> = partials
+                .into_iter()
+                .map(|partial| {
+                    let mut request = DownloadRequest::new(partial.url);
+                    if let (Some(dir), Some(name)) =
+                        (partial.path.parent(), partial.path.file_name())
+                    {
+                        request = request.with_dir(dir).with_out(name);
+                    }
+                    Ok(request)
+                })
+                .collect();
+            download(config, args, stream::iter(requests).boxed()).await?
+        }
+        Commands::Status => {
+            status(&config).await?;
+            true
+        }
+        Commands::Verify => verify(&config).await?,
+        Commands::Clean { all } => {
+            let summary = maintenance::clean(&config, all).await?;
+            for path in &summary.removed_files {
+                println!("removed {}", path.display());
+            }
+            for url in &summary.removed_entries {
+                println!("forgot {}", url);
+            }
+            println!(
+                "Removed {} files and {} cache entries",
+                summary.removed_files.len(),
+                summary.removed_entries.len()
+            );
+            true
+        }
+    };
+
+    if !success {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn url_requests(config: &Config) -> Requests {
+    let urls: Vec<_> = config
+        .urls
+        .iter()
+        .cloned()
+        .map(DownloadRequest::new)
+        .map(Ok)
+        .collect();
+    stream::iter(urls).boxed()
+}
+
+async fn open_input(path: &Path) -> Result<Box<dyn AsyncBufRead + Unpin + Send>, DownloadError> {
+    if path == Path::new("-") {
+        return Ok(Box::new(BufReader::new(tokio::io::stdin())));
+    }
+    let file = tokio::fs::File::open(path).await.map_err(|e| {
+        DownloadError::ConfigError(format!("cannot open input file {}: {}", path.display(), e))
+    })?;
+    Ok(Box::new(BufReader::new(file)))
+}
+
+/// 调用前配置已经校验过
+async fn download(
+    config: Config,
+    args: RunArgs,
+    requests: Requests,
+) -> Result<bool, DownloadError> {
     info!("Starting download process with {} workers", config.workers);
     info!("Download directory: {}", config.download_dir.display());
     info!("Random order: {}", config.random_order);
 
-    download_all_files(config).await?;
+    let control = DownloadControl::new(config.rate_limit_kb);
+    if args.interactive {
+        spawn_interactive(control.clone());
+    }
+
+    let downloader = Downloader::builder()
+        .with_config(config)
+        .with_control(control)
+        .build()
+        .await?;
+    let report = downloader.download_requests(requests).await?;
+    println!("{}", report);
+
+    if report.is_success() {
+        info!("Download process completed successfully");
+    }
+    Ok(report.is_success())
+}
 
-    info!("Download process completed successfully");
+async fn status(config: &Config) -> Result<(), DownloadError> {
+    let partials = maintenance::partial_downloads(config).await?;
+    if partials.is_empty() {
+        println!("No partial downloads");
+        return Ok(());
+    }
+
+    println!("{:>10}  {:>10}  {:>5}  URL", "DONE", "SIZE", "%");
+    for partial in partials {
+        // 没有元数据的 `.part` 无法判断进度
+        let percent = match partial.downloaded {
+            Some(downloaded) if partial.total > 0 => {
+                format!("{:.0}", downloaded as f64 / partial.total as f64 * 100.0)
+            }
+            _ => "?".to_string(),
+        };
+        let done = partial
+            .downloaded
+            .map_or_else(|| "unknown".to_string(), |n| bytesize::to_string(n, true));
+        println!(
+            "{:>10}  {:>10}  {:>5}  {}\n{:>32}-> {}",
+            done,
+            bytesize::to_string(partial.total, true),
+            percent,
+            partial.url,
+            "",
+            partial.path.display()
+        );
+    }
     Ok(())
 }
+
+/// 返回所有文件是否都通过校验
+async fn verify(config: &Config) -> Result<bool, DownloadError> {
+    let results = maintenance::verify_downloads(config).await?;
+    if results.is_empty() {
+        println!("No finished downloads to verify");
+        return Ok(true);
+    }
+
+    let mut success = true;
+    for result in results {
+        let label = match &result.status {
+            VerifyStatus::Ok => "ok".to_string(),
+            VerifyStatus::Unverifiable => "no checksum".to_string(),
+            VerifyStatus::Missing => {
+                success = false;
+                "missing".to_string()
+            }
+            VerifyStatus::Mismatch { expected, actual } => {
+                success = false;
+                format!("MISMATCH (expected {}, got {})", expected, actual)
+            }
+        };
+        println!("{:<12} {}", label, result.path.display());
+    }
+    Ok(success)
+}
+
+fn spawn_interactive(control: DownloadControl) {
+    let (mut interactive_mode, status_tx, mut command_rx) = InteractiveMode::new();
+    control.set_status_sender(status_tx);
+    tokio::spawn(async move {
+        interactive_mode.run().await;
+    });
+    tokio::spawn(async move {
+        while let Some(command) = command_rx.recv().await {
+            control.apply(command);
+        }
+    });
+}
@@ -0,0 +1,216 @@
+//! `status`、`verify`、`clean` 等命令使用的离线操作，只读取下载缓存和本地文件，不发起网络请求
+
+use crate::cache::{CacheManager, DownloadCache, PartMeta};
+use crate::checksum;
+use crate::config::{ChecksumAlgorithm, Config};
+use crate::downloader::resolve_target;
+use crate::error::DownloadError;
+use crate::utils::{part_file_path, part_meta_path};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// 缓存中记录的未完成下载
+#[derive(Debug, Clone)]
+pub struct PartialDownload {
+    pub url: String,
+    /// 最终保存路径，数据目前在其 `.part` 文件中
+    pub path: PathBuf,
+    /// `.part` 文件中可以续传的字节数；`.part` 缺少元数据、下次会从头开始时为 `None`
+    pub downloaded: Option<u64>,
+    /// 远程文件大小，未知时为 0
+    pub total: u64,
+}
+
+/// 缓存中未完成的下载，按 URL 排序
+pub async fn partial_downloads(config: &Config) -> Result<Vec<PartialDownload>, DownloadError> {
+    let cache = CacheManager::new(config.cache_dir()).await?;
+    let mut partials: Vec<_> = cache
+        .entries()
+        .filter(|entry| !entry.is_complete())
+        .filter_map(|entry| {
+            let path = target_path(config, entry)?;
+            // 分段下载的 `.part` 是预分配的，进度要从元数据中读取
+            let part = part_file_path(&path);
+            let downloaded = match std::fs::metadata(&part) {
+                Ok(metadata) => PartMeta::read(&part).map(|meta| meta.downloaded(metadata.len())),
+                Err(_) => Some(0),
+            };
+            Some(PartialDownload {
+                url: entry.url.clone(),
+                path,
+                downloaded,
+                total: entry.file_size,
+            })
+        })
+        .collect();
+    partials.sort_by(|a, b| a.url.cmp(&b.url));
+    Ok(partials)
+}
+
+/// 单个已完成文件的校验结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerifyStatus {
+    Ok,
+    Mismatch { expected: String, actual: String },
+    /// 缓存记录已完成，但文件不存在
+    Missing,
+    /// 既没有配置期望摘要，下载时也没有记录摘要
+    Unverifiable,
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyResult {
+    pub url: String,
+    pub path: PathBuf,
+    pub status: VerifyStatus,
+}
+
+/// 重新计算缓存中所有已完成文件的摘要；优先使用配置中的期望值，否则与下载时记录的摘要比较
+pub async fn verify_downloads(config: &Config) -> Result<Vec<VerifyResult>, DownloadError> {
+    let cache = CacheManager::new(config.cache_dir()).await?;
+    let mut targets: Vec<_> = cache
+        .entries()
+        .filter(|entry| entry.is_complete())
+        .filter_map(|entry| {
+            let path = target_path(config, entry)?;
+            let expected = expected_checksum(config, &entry.url, entry.checksum.as_deref());
+            Some((entry.url.clone(), path, expected))
+        })
+        .collect();
+    targets.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut results = Vec::with_capacity(targets.len());
+    for (url, path, expected) in targets {
+        let status = match expected {
+            _ if !path.exists() => VerifyStatus::Missing,
+            None => VerifyStatus::Unverifiable,
+            Some((algorithm, expected)) => {
+                let file = path.clone();
+                let actual =
+                    tokio::task::spawn_blocking(move || checksum::digest_file(&file, algorithm))
+                        .await??;
+                if actual.eq_ignore_ascii_case(&expected) {
+                    VerifyStatus::Ok
+                } else {
+                    VerifyStatus::Mismatch { expected, actual }
+                }
+            }
+        };
+        results.push(VerifyResult { url, path, status });
+    }
+    Ok(results)
+}
+
+fn expected_checksum(
+    config: &Config,
+    url: &str,
+    recorded: Option<&str>,
+) -> Option<(ChecksumAlgorithm, String)> {
+    if let Some(check) = &config.integrity_check {
+        if let Some(expected) = check.expected_checksum(url) {
+            return Some((check.algorithm, expected.to_string()));
+        }
+    }
+    let (algorithm, hex) = checksum::parse_tagged(recorded?)?;
+    Some((algorithm, hex.to_string()))
+}
+
+/// [`clean`] 删除的内容
+#[derive(Debug, Clone, Default)]
+pub struct CleanSummary {
+    pub removed_files: Vec<PathBuf>,
+    pub removed_entries: Vec<String>,
+}
+
+/// 删除无法续传的残留文件和失效的缓存记录：缓存记录对应的、缺少元数据的 `.part`，
+/// 缺少 `.part` 的元数据，以及本地文件都已不存在的缓存记录。与缓存记录无关的 `.part`
+/// 可能属于其他程序，不会删除。
+/// `all` 为 `true` 时同时放弃所有未完成的下载，并删除下载目录中所有的 `.part`
+pub async fn clean(config: &Config, all: bool) -> Result<CleanSummary, DownloadError> {
+    let mut summary = CleanSummary::default();
+    let cache_dir = config.cache_dir();
+    let mut cache = CacheManager::new(&cache_dir).await?;
+    let known: HashSet<PathBuf> = cache
+        .entries()
+        .filter_map(|entry| target_path(config, entry))
+        .map(|path| part_file_path(&path))
+        .collect();
+
+    for part in find_files(&config.download_dir, &cache_dir, ".part")? {
+        let meta = part_meta_path(&part);
+        if all || (!meta.exists() && known.contains(&part)) {
+            remove_file(&part, &mut summary)?;
+            remove_file(&meta, &mut summary)?;
+        }
+    }
+    for meta in find_files(&config.download_dir, &cache_dir, ".part.meta")? {
+        let part = meta.with_extension("");
+        if !part.exists() {
+            remove_file(&meta, &mut summary)?;
+        }
+    }
+
+    let stale: Vec<String> = cache
+        .entries()
+        .filter(|entry| match target_path(config, entry) {
+            None => true,
+            Some(path) if entry.is_complete() => !path.exists(),
+            Some(path) => all || !part_file_path(&path).exists(),
+        })
+        .map(|entry| entry.url.clone())
+        .collect();
+    for url in stale {
+        cache.remove_cache(&url);
+        summary.removed_entries.push(url);
+    }
+    summary.removed_entries.sort();
+    cache.save().await?;
+
+    Ok(summary)
+}
+
+/// 缓存记录的保存路径；旧版本的记录没有路径，按 URL 推算
+fn target_path(config: &Config, entry: &DownloadCache) -> Option<PathBuf> {
+    entry.path.clone().or_else(|| {
+        resolve_target(config, &entry.url, 0)
+            .ok()
+            .map(|(_, path)| path)
+    })
+}
+
+fn remove_file(path: &Path, summary: &mut CleanSummary) -> Result<(), DownloadError> {
+    match std::fs::remove_file(path) {
+        Ok(()) => {
+            summary.removed_files.push(path.to_path_buf());
+            Ok(())
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// 递归查找 `dir` 下文件名以 `suffix` 结尾的文件，跳过缓存目录
+fn find_files(dir: &Path, skip: &Path, suffix: &str) -> std::io::Result<Vec<PathBuf>> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path == skip {
+                continue;
+            }
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.to_string_lossy().ends_with(suffix) {
+                found.push(path);
+            }
+        }
+    }
+    found.sort();
+    Ok(found)
+}
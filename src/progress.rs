@@ -1,10 +1,10 @@
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
 use std::time::Duration;
 use std::sync::atomic::{AtomicUsize, AtomicU64, Ordering};
 use bytesize;
 
 pub struct GlobalProgress {
-    total_files: usize,
+    total_files: AtomicUsize,
     completed_files: AtomicUsize,
     total_bytes: AtomicU64,
     downloaded_bytes: AtomicU64,
@@ -27,7 +27,7 @@ impl GlobalProgress {
         main_pb.enable_steady_tick(Duration::from_millis(500));
         
         Self {
-            total_files,
+            total_files: AtomicUsize::new(total_files),
             completed_files: AtomicUsize::new(0),
             total_bytes: AtomicU64::new(0),
             downloaded_bytes: AtomicU64::new(0),
@@ -49,19 +49,29 @@ impl GlobalProgress {
         pb
     }
 
+    /// 边读取输入边下载时，每读到一个 URL 增加总数
+    pub fn add_files(&self, count: usize) {
+        self.total_files.fetch_add(count, Ordering::SeqCst);
+        self.update_display();
+    }
+
     pub fn update_progress(&self, bytes: u64) {
         self.downloaded_bytes.fetch_add(bytes, Ordering::SeqCst);
         self.update_display();
     }
 
     pub fn complete_file(&self) {
-        let completed = self.completed_files.fetch_add(1, Ordering::SeqCst) + 1;
-        if completed >= self.total_files {
-            self.main_progress.set_position(self.total_files as u64);
+        self.completed_files.fetch_add(1, Ordering::SeqCst);
+        self.update_display();
+    }
+
+    /// 所有任务结束后调用；只有全部文件都完成时才显示完成信息
+    pub fn finish(&self) {
+        let total_files = self.total_files.load(Ordering::SeqCst);
+        if self.completed_files.load(Ordering::SeqCst) >= total_files {
+            self.main_progress.set_position(total_files as u64);
             self.main_progress.finish_with_message("✨ All downloads completed successfully");
             self.multi_progress.clear().ok();
-        } else {
-            self.update_display();
         }
     }
 
@@ -70,11 +80,23 @@ impl GlobalProgress {
         self.update_display();
     }
 
+    /// 文件结束后把总大小中预计的 `expected` 字节换成实际传输的 `actual` 字节
+    pub fn adjust_total_bytes(&self, expected: u64, actual: u64) {
+        let _ = self
+            .total_bytes
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |total| {
+                Some(total.saturating_sub(expected) + actual)
+            });
+        self.update_display();
+    }
+
     fn update_display(&self) {
         let completed = self.completed_files.load(Ordering::SeqCst);
+        let total_files = self.total_files.load(Ordering::SeqCst);
         let downloaded = self.downloaded_bytes.load(Ordering::SeqCst);
         let total = self.total_bytes.load(Ordering::SeqCst);
         
+        self.main_progress.set_length(total_files as u64);
         self.main_progress.set_position(completed as u64);
         
         let total_str = if total > 0 { 
@@ -83,20 +105,30 @@ impl GlobalProgress {
             "?".to_string() 
         };
         
-        let percentage = if self.total_files > 0 {
-            (completed as f64 / self.total_files as f64 * 100.0) as u32
+        let percentage = if total_files > 0 {
+            (completed as f64 / total_files as f64 * 100.0) as u32
         } else {
             0
         };
         
-        let msg = format!(
+        let mut msg = format!(
             "Progress: {}/{} files ({}%) - {}/{}",
             completed,
-            self.total_files,
+            total_files,
             percentage,
             bytesize::to_string(downloaded, true),
             total_str
         );
+
+        // 总大小已知时按平均速度估算剩余时间
+        let elapsed = self.main_progress.elapsed().as_secs_f64();
+        if total > downloaded && downloaded > 0 && elapsed > 0.0 {
+            let remaining = (total - downloaded) as f64 * elapsed / downloaded as f64;
+            msg.push_str(&format!(
+                " - ETA {}",
+                HumanDuration(Duration::from_secs_f64(remaining))
+            ));
+        }
         self.main_progress.set_message(msg);
     }
 }
@@ -0,0 +1,95 @@
+use crate::error::DownloadError;
+use crate::stats::DownloadSummary;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// 单个 URL 的最终结果
+#[derive(Debug)]
+pub enum DownloadOutcome {
+    Completed,
+    /// 未发起下载，附带跳过原因
+    Skipped(String),
+    Failed(DownloadError),
+}
+
+impl DownloadOutcome {
+    pub fn label(&self) -> &'static str {
+        match self {
+            DownloadOutcome::Completed => "completed",
+            DownloadOutcome::Skipped(_) => "skipped",
+            DownloadOutcome::Failed(_) => "failed",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UrlReport {
+    /// URL 在本批输入中的位置
+    pub index: usize,
+    pub url: String,
+    pub outcome: DownloadOutcome,
+    /// 本地保存路径；URL 无效时为 `None`
+    pub path: Option<PathBuf>,
+    /// 本次运行写入的字节数
+    pub bytes: u64,
+    pub duration: Duration,
+}
+
+/// 一次批量下载的完整结果，按 URL 原始顺序排列
+#[derive(Debug)]
+pub struct DownloadReport {
+    pub entries: Vec<UrlReport>,
+    pub summary: DownloadSummary,
+}
+
+impl DownloadReport {
+    /// 没有任何 URL 失败
+    pub fn is_success(&self) -> bool {
+        self.failures().next().is_none()
+    }
+
+    pub fn completed(&self) -> impl Iterator<Item = &UrlReport> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, DownloadOutcome::Completed))
+    }
+
+    pub fn skipped(&self) -> impl Iterator<Item = &UrlReport> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, DownloadOutcome::Skipped(_)))
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &UrlReport> {
+        self.entries
+            .iter()
+            .filter(|e| matches!(e.outcome, DownloadOutcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for DownloadReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<9}  {:>10}  {:>8}  URL", "STATUS", "BYTES", "TIME")?;
+        for entry in &self.entries {
+            write!(
+                f,
+                "{:<9}  {:>10}  {:>7.1}s  {}",
+                entry.outcome.label(),
+                bytesize::to_string(entry.bytes, true),
+                entry.duration.as_secs_f64(),
+                entry.url
+            )?;
+            match (&entry.outcome, &entry.path) {
+                (DownloadOutcome::Failed(e), _) => write!(f, "\n{:>34}{}", "", e)?,
+                (DownloadOutcome::Skipped(reason), _) => write!(f, "\n{:>34}{}", "", reason)?,
+                (DownloadOutcome::Completed, Some(path)) => {
+                    write!(f, "\n{:>34}-> {}", "", path.display())?
+                }
+                (DownloadOutcome::Completed, None) => {}
+            }
+            writeln!(f)?;
+        }
+        write!(f, "Total: {}", self.summary)
+    }
+}
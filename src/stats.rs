@@ -1,27 +1,53 @@
-use std::time::SystemTime;
+use std::fmt;
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
 
+/// Counters for one or more runs. When the same stats are shared by several runs (see
+/// `DownloaderBuilder::with_stats`), the elapsed time only counts time spent running,
+/// not the idle time between runs.
 #[derive(Debug)]
 pub struct DownloadStats {
     pub start_time: SystemTime,
-    pub end_time: Option<SystemTime>,
+    /// When the last run finished; `None` while a run is in progress.
+    pub end_time: Mutex<Option<SystemTime>>,
     pub total_bytes: AtomicU64,
     pub successful_downloads: AtomicUsize,
     pub failed_downloads: AtomicUsize,
+    pub skipped_downloads: AtomicUsize,
     pub retry_count: AtomicUsize,
     pub average_speed: AtomicU64,
+    activity: Mutex<Activity>,
+}
+
+/// Time spent running, accumulated across runs.
+#[derive(Debug)]
+struct Activity {
+    /// Duration of the finished runs.
+    active: Duration,
+    /// Start of the current stretch of activity, if any.
+    since: Option<Instant>,
+    /// Runs currently in progress.
+    running: usize,
 }
 
 impl Default for DownloadStats {
     fn default() -> Self {
         Self {
             start_time: SystemTime::now(),
-            end_time: None,
+            end_time: Mutex::new(None),
             total_bytes: AtomicU64::new(0),
             successful_downloads: AtomicUsize::new(0),
             failed_downloads: AtomicUsize::new(0),
+            skipped_downloads: AtomicUsize::new(0),
             retry_count: AtomicUsize::new(0),
             average_speed: AtomicU64::new(0),
+            // Stats used without `begin` count from their creation
+            activity: Mutex::new(Activity {
+                active: Duration::ZERO,
+                since: Some(Instant::now()),
+                running: 0,
+            }),
         }
     }
 }
@@ -37,16 +63,96 @@ impl DownloadStats {
         self.failed_downloads.fetch_add(1, Ordering::SeqCst);
     }
 
+    pub fn record_skip(&self) {
+        self.skipped_downloads.fetch_add(1, Ordering::SeqCst);
+    }
+
     pub fn record_retry(&self) {
         self.retry_count.fetch_add(1, Ordering::SeqCst);
     }
 
-    fn update_speed(&self) {
-        if let Ok(duration) = SystemTime::now().duration_since(self.start_time) {
-            let seconds = duration.as_secs().max(1);
-            let bytes = self.total_bytes.load(Ordering::SeqCst);
-            let speed = bytes / seconds;
-            self.average_speed.store(speed, Ordering::SeqCst);
+    /// Marks the start of a run; the elapsed time advances until the matching
+    /// [`finish`](Self::finish). Runs may overlap.
+    pub fn begin(&self) {
+        let mut activity = self.activity.lock().unwrap();
+        if activity.running == 0 {
+            // Idle time since the last run does not count
+            activity.since = Some(Instant::now());
+        }
+        activity.running += 1;
+        *self.end_time.lock().unwrap() = None;
+    }
+
+    /// Marks a run as finished; once no run is in progress the elapsed time and speed
+    /// stop advancing.
+    pub fn finish(&self) {
+        {
+            let mut activity = self.activity.lock().unwrap();
+            activity.running = activity.running.saturating_sub(1);
+            if activity.running == 0 {
+                if let Some(since) = activity.since.take() {
+                    activity.active += since.elapsed();
+                    *self.end_time.lock().unwrap() = Some(SystemTime::now());
+                }
+            }
+        }
+        self.update_speed();
+    }
+
+    /// Time spent running, including the current run.
+    pub fn elapsed(&self) -> Duration {
+        let activity = self.activity.lock().unwrap();
+        let current = activity
+            .since
+            .map_or(Duration::ZERO, |since| since.elapsed());
+        activity.active + current
+    }
+
+    pub fn summary(&self) -> DownloadSummary {
+        DownloadSummary {
+            total_bytes: self.total_bytes.load(Ordering::SeqCst),
+            successful_downloads: self.successful_downloads.load(Ordering::SeqCst),
+            failed_downloads: self.failed_downloads.load(Ordering::SeqCst),
+            skipped_downloads: self.skipped_downloads.load(Ordering::SeqCst),
+            retry_count: self.retry_count.load(Ordering::SeqCst),
+            elapsed: self.elapsed(),
+            average_speed: self.average_speed.load(Ordering::SeqCst),
         }
     }
-} 
\ No newline at end of file
+
+    fn update_speed(&self) {
+        let seconds = self.elapsed().as_secs_f64().max(0.001);
+        let bytes = self.total_bytes.load(Ordering::SeqCst);
+        let speed = (bytes as f64 / seconds) as u64;
+        self.average_speed.store(speed, Ordering::SeqCst);
+    }
+}
+
+/// A point-in-time copy of [`DownloadStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadSummary {
+    pub total_bytes: u64,
+    pub successful_downloads: usize,
+    pub failed_downloads: usize,
+    pub skipped_downloads: usize,
+    pub retry_count: usize,
+    pub elapsed: Duration,
+    /// Bytes per second of time spent running.
+    pub average_speed: u64,
+}
+
+impl fmt::Display for DownloadSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} succeeded, {} skipped, {} failed, {} retries, {} in {:.1}s ({}/s)",
+            self.successful_downloads,
+            self.skipped_downloads,
+            self.failed_downloads,
+            self.retry_count,
+            bytesize::to_string(self.total_bytes, true),
+            self.elapsed.as_secs_f64(),
+            bytesize::to_string(self.average_speed, true)
+        )
+    }
+}
@@ -0,0 +1,215 @@
+//! 输出路径模板，例如 `{host}/{path}`、`{index:03}-{name}`、`{date}/{name}`
+//!
+//! 可用的字段：
+//!
+//! - `{host}`：URL 的主机名
+//! - `{dir}`：URL 路径中文件名之前的目录，经过百分号解码
+//! - `{path}`：`{dir}/{name}`，镜像时保留远程目录结构
+//! - `{name}`、`{stem}`、`{ext}`：最终文件名、去掉扩展名的部分和扩展名（不含点）
+//! - `{index}`：URL 在本批中的序号，从 0 开始；`{index:03}` 补零到 3 位
+//! - `{date}`：本批开始时的 UTC 日期，`YYYY-MM-DD`
+//!
+//! 展开结果按 `/` 拆分后逐段清理；任何一段是 `..` 时拒绝整个路径，远程名称无法借此跳出保存目录
+
+use crate::filename;
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// 解析后的输出路径模板
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+    /// `{index:0N}`
+    Index {
+        width: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Host,
+    Dir,
+    Path,
+    Name,
+    Stem,
+    Ext,
+    Date,
+}
+
+/// 展开模板所需的信息
+#[derive(Debug, Clone, Copy)]
+pub struct TemplateVars<'a> {
+    pub url: &'a Url,
+    /// 已解析的文件名
+    pub name: &'a str,
+    pub index: usize,
+    /// `YYYY-MM-DD`，通常取自 [`today`]
+    pub date: &'a str,
+}
+
+impl OutputTemplate {
+    pub fn parse(template: &str) -> Result<Self, String> {
+        if template.starts_with('/') || template.starts_with('\\') {
+            return Err(format!("template must be a relative path: {:?}", template));
+        }
+
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find('{') {
+            if open > 0 {
+                parts.push(Part::Literal(rest[..open].to_string()));
+            }
+            let close = rest[open..]
+                .find('}')
+                .map(|i| open + i)
+                .ok_or_else(|| format!("unclosed '{{' in template {:?}", template))?;
+            parts.push(parse_field(&rest[open + 1..close])?);
+            rest = &rest[close + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts.is_empty() {
+            return Err("template must not be empty".to_string());
+        }
+        Ok(Self { parts })
+    }
+
+    /// 展开为相对路径；结果为空或包含 `..` 时返回错误
+    pub fn render(&self, vars: &TemplateVars<'_>) -> Result<PathBuf, String> {
+        let mut expanded = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => expanded.push_str(text),
+                Part::Index { width } => {
+                    expanded.push_str(&format!("{:0width$}", vars.index, width = *width))
+                }
+                Part::Field(field) => expanded.push_str(&field_value(*field, vars)),
+            }
+        }
+
+        let mut path = PathBuf::new();
+        for component in expanded.split(['/', '\\']) {
+            let component = component.trim();
+            if component == ".." {
+                return Err(format!(
+                    "path {:?} escapes the download directory",
+                    expanded
+                ));
+            }
+            if let Some(component) = filename::sanitize(component) {
+                path.push(component);
+            }
+        }
+        if path.as_os_str().is_empty() {
+            return Err(format!(
+                "template expands to an empty path for {}",
+                vars.url
+            ));
+        }
+        Ok(path)
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Part, String> {
+    let (name, format) = match spec.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (spec, None),
+    };
+    if name == "index" {
+        let width = match format {
+            None => 0,
+            Some(format) => format
+                .parse()
+                .map_err(|_| format!("invalid width {:?} for {{index}}", format))?,
+        };
+        return Ok(Part::Index { width });
+    }
+    if format.is_some() {
+        return Err(format!("only {{index}} accepts a format, got {{{}}}", spec));
+    }
+
+    let field = match name {
+        "host" => Field::Host,
+        "dir" => Field::Dir,
+        "path" => Field::Path,
+        "name" => Field::Name,
+        "stem" => Field::Stem,
+        "ext" => Field::Ext,
+        "date" => Field::Date,
+        other => return Err(format!("unknown template field {{{}}}", other)),
+    };
+    Ok(Part::Field(field))
+}
+
+fn field_value(field: Field, vars: &TemplateVars<'_>) -> String {
+    match field {
+        Field::Host => vars.url.host_str().unwrap_or_default().to_string(),
+        Field::Dir => remote_dir(vars.url),
+        Field::Path => {
+            let dir = remote_dir(vars.url);
+            if dir.is_empty() {
+                vars.name.to_string()
+            } else {
+                format!("{}/{}", dir, vars.name)
+            }
+        }
+        Field::Name => vars.name.to_string(),
+        Field::Stem => split_name(vars.name).0.to_string(),
+        Field::Ext => split_name(vars.name).1.to_string(),
+        Field::Date => vars.date.to_string(),
+    }
+}
+
+/// URL 路径去掉最后一个非空段后的部分，逐段解码
+fn remote_dir(url: &Url) -> String {
+    let Some(segments) = url.path_segments() else {
+        return String::new();
+    };
+    let mut segments: Vec<_> = segments.filter(|s| !s.is_empty()).collect();
+    segments.pop();
+    segments
+        .iter()
+        .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn split_name(name: &str) -> (&str, &str) {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    }
+}
+
+/// 当前的 UTC 日期，`YYYY-MM-DD`
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// 1970-01-01 起的天数换算为公历日期（Howard Hinnant 的算法）
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -1,7 +1,8 @@
 use md5::Context;
 use std::fs::File;
 use std::io::{self, Read};
-use std::path::Path;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
 pub fn calculate_md5(path: &Path) -> Result<String, io::Error> {
     let mut file = File::open(path)?;
@@ -18,3 +19,19 @@ pub fn calculate_md5(path: &Path) -> Result<String, io::Error> {
 
     Ok(format!("{:x}", context.compute()))
 }
+
+/// 下载过程中写入数据的临时文件：`<name>.part`
+pub fn part_file_path(path: &Path) -> PathBuf {
+    append_suffix(path, ".part")
+}
+
+/// `.part` 文件旁记录续传元数据的文件：`<name>.part.meta`
+pub fn part_meta_path(part_path: &Path) -> PathBuf {
+    append_suffix(part_path, ".meta")
+}
+
+fn append_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
@@ -0,0 +1,391 @@
+//! 测试用的最小 HTTP 服务器，支持 Range 请求并记录收到的请求
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone, Default)]
+pub struct Route {
+    pub body: Vec<u8>,
+    pub accept_ranges: bool,
+    pub headers: Vec<(String, String)>,
+    /// 剩余需要中途断开的 GET 次数：只发送一半内容就关闭连接
+    pub truncate_remaining: Arc<AtomicUsize>,
+    /// 剩余需要中途停顿的 GET 次数：发送一半内容后保持连接但不再发送
+    pub stall_remaining: Arc<AtomicUsize>,
+    /// 剩余需要以 `fail_status` 拒绝的 GET 次数
+    pub fail_remaining: Arc<AtomicUsize>,
+    pub fail_status: u16,
+    /// 拒绝时带上的 `Retry-After`
+    pub retry_after: Option<String>,
+    /// 每个请求在响应前等待的时间，等待期间计入并发请求数
+    pub delay: Option<Duration>,
+    /// 不发送 Content-Length，以关闭连接表示内容结束
+    pub unknown_length: bool,
+    /// 以 302 重定向到该路径
+    pub redirect: Option<String>,
+    /// HEAD 请求返回 405
+    pub no_head: bool,
+    /// 声明的 Content-Length，与实际发送的内容无关
+    pub claimed_length: Option<u64>,
+    /// 剩余的请求次数用完后换成新的路由，模拟远程文件被替换
+    pub replaced: Option<(Arc<AtomicUsize>, Box<Route>)>,
+}
+
+impl Route {
+    pub fn new(body: Vec<u8>) -> Self {
+        Self {
+            body,
+            ..Self::default()
+        }
+    }
+
+    pub fn ranged(body: Vec<u8>) -> Self {
+        Self {
+            body,
+            accept_ranges: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn redirect(location: &str) -> Self {
+        Self {
+            redirect: Some(location.to_string()),
+            ..Self::default()
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn unknown_length(mut self) -> Self {
+        self.unknown_length = true;
+        self
+    }
+
+    pub fn claim_length(mut self, len: u64) -> Self {
+        self.claimed_length = Some(len);
+        self
+    }
+
+    pub fn no_head(mut self) -> Self {
+        self.no_head = true;
+        self
+    }
+
+    pub fn truncate_first(self, times: usize) -> Self {
+        self.truncate_remaining.store(times, Ordering::SeqCst);
+        self
+    }
+
+    pub fn fail_first(mut self, times: usize, status: u16) -> Self {
+        self.fail_remaining.store(times, Ordering::SeqCst);
+        self.fail_status = status;
+        self
+    }
+
+    pub fn retry_after(mut self, value: &str) -> Self {
+        self.retry_after = Some(value.to_string());
+        self
+    }
+
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    pub fn stall_first(self, times: usize) -> Self {
+        self.stall_remaining.store(times, Ordering::SeqCst);
+        self
+    }
+
+    pub fn replaced_after(mut self, requests: usize, next: Route) -> Self {
+        self.replaced = Some((Arc::new(AtomicUsize::new(requests)), Box::new(next)));
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+}
+
+pub struct TestServer {
+    addr: SocketAddr,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    concurrency: Arc<Concurrency>,
+}
+
+/// 带 `delay` 的路由上同时在等待的请求数
+#[derive(Debug, Default)]
+struct Concurrency {
+    active: AtomicUsize,
+    max: AtomicUsize,
+}
+
+impl TestServer {
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let routes = Arc::new(Mutex::new(HashMap::new()));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let concurrency = Arc::new(Concurrency::default());
+
+        let (task_routes, task_requests, task_concurrency) =
+            (routes.clone(), requests.clone(), concurrency.clone());
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let routes = task_routes.clone();
+                let requests = task_requests.clone();
+                let concurrency = task_concurrency.clone();
+                tokio::spawn(async move {
+                    let _ = handle_connection(socket, routes, requests, concurrency).await;
+                });
+            }
+        });
+
+        Self {
+            addr,
+            routes,
+            requests,
+            concurrency,
+        }
+    }
+
+    /// 带 `delay` 的路由上曾经同时等待的最多请求数
+    pub fn max_concurrent(&self) -> usize {
+        self.concurrency.max.load(Ordering::SeqCst)
+    }
+
+    pub fn route(&self, path: &str, route: Route) {
+        self.routes.lock().unwrap().insert(path.to_string(), route);
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn requests_for(&self, method: &str, path: &str) -> Vec<RecordedRequest> {
+        self.requests()
+            .into_iter()
+            .filter(|r| r.method == method && r.path == path)
+            .collect()
+    }
+}
+
+/// 生成确定性的测试数据
+pub fn sample_body(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+async fn handle_connection(
+    mut socket: TcpStream,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+    concurrency: Arc<Concurrency>,
+) -> std::io::Result<()> {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+        let n = socket.read(&mut chunk).await?;
+        if n == 0 {
+            return Ok(());
+        }
+        buf.extend_from_slice(&chunk[..n]);
+    }
+
+    let text = String::from_utf8_lossy(&buf).to_string();
+    let mut lines = text.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let method = request_line.next().unwrap_or_default().to_string();
+    let path = request_line.next().unwrap_or_default().to_string();
+    let headers: HashMap<String, String> = lines
+        .take_while(|l| !l.is_empty())
+        .filter_map(|l| l.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+
+    let request = RecordedRequest {
+        method,
+        path,
+        headers,
+    };
+    requests.lock().unwrap().push(request.clone());
+
+    let route = routes.lock().unwrap().get(&request.path).cloned();
+    let route = match route {
+        Some(Route {
+            replaced: Some((remaining, next)),
+            ..
+        }) if remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_err() =>
+        {
+            Some(*next)
+        }
+        route => route,
+    };
+    let Some(route) = route else {
+        socket
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return Ok(());
+    };
+
+    if route.no_head && request.method == "HEAD" {
+        socket
+            .write_all(b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+            .await?;
+        return socket.shutdown().await;
+    }
+
+    if let Some(location) = &route.redirect {
+        let head = format!(
+            "HTTP/1.1 302 Found\r\nLocation: {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            location
+        );
+        socket.write_all(head.as_bytes()).await?;
+        return socket.shutdown().await;
+    }
+
+    // 在发出任何响应之前计数，客户端此时一定还持有这个连接
+    if let Some(delay) = route.delay {
+        let active = concurrency.active.fetch_add(1, Ordering::SeqCst) + 1;
+        concurrency.max.fetch_max(active, Ordering::SeqCst);
+        tokio::time::sleep(delay).await;
+        concurrency.active.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    let fail = request.method == "GET"
+        && route
+            .fail_remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+    if fail {
+        let mut head = format!(
+            "HTTP/1.1 {} Error\r\nContent-Length: 0\r\nConnection: close\r\n",
+            route.fail_status
+        );
+        if let Some(retry_after) = &route.retry_after {
+            head.push_str(&format!("Retry-After: {}\r\n", retry_after));
+        }
+        head.push_str("\r\n");
+        socket.write_all(head.as_bytes()).await?;
+        return socket.shutdown().await;
+    }
+
+    let total = route.body.len() as u64;
+    // If-Range 与当前 ETag 不一致时忽略 Range，返回完整内容
+    let etag = route
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("etag"))
+        .map(|(_, v)| v.as_str());
+    let if_range_ok = request.header("if-range").is_none_or(|v| Some(v) == etag);
+    let range_header = request
+        .header("range")
+        .filter(|_| route.accept_ranges && if_range_ok);
+
+    if let Some(start) = range_header.and_then(range_start) {
+        if start >= total {
+            let head = format!(
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                total
+            );
+            socket.write_all(head.as_bytes()).await?;
+            return socket.shutdown().await;
+        }
+    }
+    let range = range_header.and_then(|r| parse_range(r, total));
+
+    let (status, body, mut extra) = match range {
+        Some((start, end)) => (
+            "206 Partial Content",
+            &route.body[start as usize..=end as usize],
+            vec![format!("Content-Range: bytes {}-{}/{}", start, end, total)],
+        ),
+        None => ("200 OK", &route.body[..], Vec::new()),
+    };
+    if route.accept_ranges {
+        extra.push("Accept-Ranges: bytes".to_string());
+    }
+    extra.extend(route.headers.iter().map(|(k, v)| format!("{}: {}", k, v)));
+
+    let mut head = format!("HTTP/1.1 {}\r\nConnection: close\r\n", status);
+    if let Some(len) = route.claimed_length {
+        head.push_str(&format!("Content-Length: {}\r\n", len));
+    } else if !route.unknown_length {
+        head.push_str(&format!("Content-Length: {}\r\n", body.len()));
+    }
+    for line in extra {
+        head.push_str(&line);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+
+    socket.write_all(head.as_bytes()).await?;
+    if request.method != "HEAD" {
+        let truncate = route
+            .truncate_remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        let stall = route
+            .stall_remaining
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        let body = if truncate || stall {
+            &body[..body.len() / 2]
+        } else {
+            body
+        };
+        socket.write_all(body).await?;
+        if stall {
+            tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+        }
+    }
+    socket.shutdown().await
+}
+
+fn range_start(header: &str) -> Option<u64> {
+    header
+        .strip_prefix("bytes=")?
+        .split_once('-')?
+        .0
+        .parse()
+        .ok()
+}
+
+fn parse_range(header: &str, total: u64) -> Option<(u64, u64)> {
+    let spec = header.strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let start: u64 = start.parse().ok()?;
+    let end = if end.is_empty() {
+        total.checked_sub(1)?
+    } else {
+        end.parse::<u64>().ok()?.min(total.checked_sub(1)?)
+    };
+    (start <= end).then_some((start, end))
+}
@@ -1,20 +1,38 @@
 use multhreadown::{
+    auth::{self, Netrc},
     cache::CacheManager,
     cli::{Command, DownloadStatus, InteractiveMode},
-    config::{Config, RetryConfig},
-    downloader,
+    config::{
+        self, AuthConfig, ChecksumAlgorithm, CollisionPolicy, Config, ConfigError, ConfigLayers,
+        DownloadFilter, IntegrityCheck, MismatchPolicy, ProxyConfig, RetryConfig, TlsConfig,
+    },
+    control::DownloadControl,
+    downloader::{self, Downloader},
     error::DownloadError,
     events::DownloadEventHandler,
+    filename,
+    input::{self, DownloadRequest, InputError},
+    report::DownloadOutcome,
+    limiter::RateLimiter,
+    maintenance::{self, VerifyStatus},
     stats::DownloadStats,
+    template::{OutputTemplate, TemplateVars},
+    utils::{part_file_path, part_meta_path},
 };
-use std::{sync::Arc, collections::HashMap};
+use std::{sync::Arc, collections::HashMap, time::Duration};
 use async_trait::async_trait;
+use futures_util::StreamExt;
+
+mod common;
+use common::{sample_body, Route, TestServer};
 
 // 测试事件处理器
 #[derive(Default)]
 struct TestEventHandler {
     start_count: std::sync::atomic::AtomicUsize,
     complete_count: std::sync::atomic::AtomicUsize,
+    error_count: std::sync::atomic::AtomicUsize,
+    progress_count: std::sync::atomic::AtomicUsize,
 }
 
 #[async_trait]
@@ -23,13 +41,18 @@ impl DownloadEventHandler for TestEventHandler {
         self.start_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
     }
 
-    async fn on_download_progress(&self, _url: &str, _progress: f64) {}
+    async fn on_download_progress(&self, _url: &str, progress: f64) {
+        assert!((0.0..=1.0).contains(&progress));
+        self.progress_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
 
     async fn on_download_complete(&self, _url: &str) {
         self.complete_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
     }
 
-    async fn on_download_error(&self, _url: &str, _error: &multhreadown::error::DownloadError) {}
+    async fn on_download_error(&self, _url: &str, _error: &multhreadown::error::DownloadError) {
+        self.error_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
 }
 
 #[tokio::test]
@@ -47,6 +70,7 @@ async fn test_basic_download() {
         retry: RetryConfig::default(),
         concurrent_downloads: 2,
         connection_timeout: 30,
+        ..Config::default()
     };
 
     let result = tokio::time::timeout(
@@ -56,7 +80,8 @@ async fn test_basic_download() {
 
     assert!(result.is_ok(), "Download timed out");
     if let Ok(download_result) = result {
-        assert!(download_result.is_ok(), "Download failed: {:?}", download_result.err());
+        let report = download_result.expect("Download could not run");
+        assert!(report.is_success(), "Download failed:\n{}", report);
     }
 }
 
@@ -72,14 +97,42 @@ async fn test_cache_manager() {
         etag: Some("abc123".to_string()),
         last_modified: Some("Thu, 01 Jan 2024 00:00:00 GMT".to_string()),
         checksum: Some("d41d8cd98f00b204e9800998ecf8427e".to_string()),
+        path: None,
     };
 
     cache_manager.update_cache(cache.url.clone(), cache);
-    let saved = cache_manager.get_cache(&"https://example.com/test.zip");
+    let saved = cache_manager.get_cache("https://example.com/test.zip");
     assert!(saved.is_some());
 
     // 测试缓存持久化
     assert!(cache_manager.save().await.is_ok());
+
+    // 按保存路径索引，记录改路径或删除时同步更新
+    let path = temp_dir.path().join("test.zip");
+    let mut entry = cache_manager.get_cache("https://example.com/test.zip").cloned().unwrap();
+    entry.path = Some(path.clone());
+    cache_manager.update_cache(entry.url.clone(), entry.clone());
+    assert_eq!(cache_manager.urls_at(&path).collect::<Vec<_>>(), ["https://example.com/test.zip"]);
+    cache_manager.save().await.unwrap();
+    // 写入临时文件后重命名，不留下临时文件
+    assert!(!temp_dir.path().join("download_cache.json.tmp").exists());
+
+    let mut reloaded = CacheManager::new(temp_dir.path()).await.unwrap();
+    assert_eq!(reloaded.urls_at(&path).count(), 1);
+    entry.path = Some(temp_dir.path().join("other.zip"));
+    reloaded.update_cache(entry.url.clone(), entry.clone());
+    assert_eq!(reloaded.urls_at(&path).count(), 0);
+    reloaded.remove_cache(&entry.url);
+    assert_eq!(reloaded.urls_at(&temp_dir.path().join("other.zip")).count(), 0);
+
+    // 损坏的缓存文件被保留下来，而不是在下次保存时被覆盖
+    std::fs::write(temp_dir.path().join("download_cache.json"), "{\"https://exa").unwrap();
+    let cache_manager = CacheManager::new(temp_dir.path()).await.unwrap();
+    assert!(cache_manager.get_cache("https://example.com/test.zip").is_none());
+    assert_eq!(
+        std::fs::read_to_string(temp_dir.path().join("download_cache.json.corrupt")).unwrap(),
+        "{\"https://exa"
+    );
 }
 
 #[tokio::test]
@@ -111,6 +164,7 @@ async fn test_invalid_url() {
         retry: RetryConfig::default(),
         concurrent_downloads: 1,
         connection_timeout: 5,
+        ..Config::default()
     };
 
     let result = tokio::time::timeout(
@@ -120,12 +174,14 @@ async fn test_invalid_url() {
 
     assert!(result.is_ok(), "Timeout error");
     if let Ok(download_result) = result {
-        assert!(download_result.is_err(), "Expected error for invalid URL");
-        if let Err(e) = download_result {
-            match e {
-                DownloadError::InvalidUrl(_) | DownloadError::UrlParseError(_) => (),
-                _ => panic!("Unexpected error type: {:?}", e),
-            }
+        let report = download_result.expect("Download could not run");
+        assert!(!report.is_success(), "Expected error for invalid URL");
+        let failure = report.failures().next().unwrap();
+        assert!(failure.path.is_none());
+        match &failure.outcome {
+            DownloadOutcome::Failed(DownloadError::InvalidUrl(_))
+            | DownloadOutcome::Failed(DownloadError::UrlParseError(_)) => (),
+            other => panic!("Unexpected outcome: {:?}", other),
         }
     }
 }
@@ -189,10 +245,11 @@ async fn test_integrity_check() {
         retry: RetryConfig::default(),
         concurrent_downloads: 1,
         connection_timeout: 30,
+        ..Config::default()
     };
 
-    let result = downloader::download_all_files(config).await;
-    assert!(result.is_ok());
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
 }
 
 #[tokio::test]
@@ -210,8 +267,2302 @@ async fn test_download_filter() {
         retry: RetryConfig::default(),
         concurrent_downloads: 1,
         connection_timeout: 30,
+        ..Config::default()
     };
 
-    let result = downloader::download_all_files(config).await;
-    assert!(result.is_ok());
-} 
\ No newline at end of file
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+}
+
+#[tokio::test]
+async fn test_segmented_download() {
+    let server = TestServer::start().await;
+    let body = sample_body(10_000);
+    server.route("/large.bin", Route::ranged(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/large.bin")],
+        segments: 4,
+        min_segment_size: 1024,
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let downloaded = std::fs::read(temp_dir.path().join("large.bin")).unwrap();
+    assert_eq!(downloaded, body);
+
+    let ranged_gets: Vec<_> = server
+        .requests_for("GET", "/large.bin")
+        .into_iter()
+        .filter_map(|r| r.header("range").map(str::to_string))
+        .collect();
+    assert_eq!(ranged_gets.len(), 4, "expected one GET per segment: {:?}", ranged_gets);
+}
+
+#[tokio::test]
+async fn test_segmented_download_falls_back_without_range_support() {
+    let server = TestServer::start().await;
+    let body = sample_body(10_000);
+    server.route("/plain.bin", Route::new(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/plain.bin")],
+        segments: 4,
+        min_segment_size: 1024,
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let downloaded = std::fs::read(temp_dir.path().join("plain.bin")).unwrap();
+    assert_eq!(downloaded, body);
+
+    let gets = server.requests_for("GET", "/plain.bin");
+    assert_eq!(gets.len(), 1);
+    assert!(gets[0].header("range").is_none());
+}
+
+#[test]
+fn test_retry_delay_backoff() {
+    let retry = RetryConfig {
+        max_retries: 5,
+        initial_delay: 1,
+        max_delay: 5,
+        backoff_factor: 2.0,
+        jitter: false,
+    };
+    let delays: Vec<u64> = (1..=5).map(|n| retry.delay_for(n).as_secs()).collect();
+    assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+    let jittered = RetryConfig { jitter: true, ..retry };
+    for attempt in 1..=5 {
+        let delay = jittered.delay_for(attempt).as_secs_f64();
+        let full = retry.delay_for(attempt).as_secs_f64();
+        assert!(
+            delay >= full / 2.0 && delay <= full,
+            "{} not in [{}, {}]",
+            delay,
+            full / 2.0,
+            full
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_retry_resumes_after_mid_stream_failure() {
+    let server = TestServer::start().await;
+    let body = sample_body(8_000);
+    server.route("/flaky.bin", Route::ranged(body.clone()).truncate_first(1));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/flaky.bin")],
+        segments: 1,
+        retry: RetryConfig {
+            max_retries: 2,
+            initial_delay: 0,
+            max_delay: 0,
+            backoff_factor: 2.0,
+            jitter: false,
+        },
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let downloaded = std::fs::read(temp_dir.path().join("flaky.bin")).unwrap();
+    assert_eq!(downloaded, body);
+
+    let gets = server.requests_for("GET", "/flaky.bin");
+    assert_eq!(gets.len(), 2);
+    assert_eq!(gets[1].header("range"), Some("bytes=4000-"));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_rate_limiter_caps_throughput() {
+    let limiter = RateLimiter::new(Some(100));
+    let start = tokio::time::Instant::now();
+
+    // 首秒的突发额度之后，剩余 200KB 需要按 100KB/s 等待
+    for _ in 0..3 {
+        limiter.acquire(100 * 1024).await;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    assert!((1.9..2.1).contains(&elapsed), "elapsed {}s", elapsed);
+
+    limiter.set_rate_limit(None);
+    assert_eq!(limiter.rate_limit_kb(), None);
+    let start = tokio::time::Instant::now();
+    limiter.acquire(10 * 1024 * 1024).await;
+    assert_eq!(start.elapsed().as_secs_f64(), 0.0);
+
+    limiter.set_rate_limit(Some(50));
+    assert_eq!(limiter.rate_limit_kb(), Some(50));
+}
+
+#[tokio::test]
+async fn test_download_events_are_fired() {
+    let server = TestServer::start().await;
+    server.route("/a.bin", Route::new(sample_body(2_000)));
+    server.route("/b.bin", Route::ranged(sample_body(4_000)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 2,
+        urls: vec![
+            server.url("/a.bin"),
+            server.url("/b.bin"),
+            server.url("/missing.bin"),
+        ],
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let handler = Arc::new(TestEventHandler::default());
+    let report = downloader::download_all_files_with_handler(config, handler.clone())
+        .await
+        .unwrap();
+    assert_eq!(report.failures().count(), 1);
+
+    let count = |c: &std::sync::atomic::AtomicUsize| c.load(std::sync::atomic::Ordering::SeqCst);
+    assert_eq!(count(&handler.start_count), 3);
+    assert_eq!(count(&handler.complete_count), 2);
+    assert_eq!(count(&handler.error_count), 1);
+    assert!(count(&handler.progress_count) >= 2);
+}
+
+#[tokio::test]
+async fn test_stats_are_populated_on_failure() {
+    let server = TestServer::start().await;
+    server.route("/ok.bin", Route::new(sample_body(3_000)));
+    // 404 不会重试，用持续返回 502 的 URL 计入重试次数
+    server.route("/broken.bin", Route::new(Vec::new()).fail_first(usize::MAX, 502));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 2,
+        urls: vec![server.url("/ok.bin"), server.url("/broken.bin")],
+        retry: RetryConfig {
+            max_retries: 1,
+            initial_delay: 0,
+            max_delay: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let stats = Arc::new(DownloadStats::default());
+    let report = downloader::download_all_files_with_stats(config, stats.clone())
+        .await
+        .unwrap();
+    assert!(!report.is_success());
+
+    assert!(stats.end_time.lock().unwrap().is_some());
+    let summary = stats.summary();
+    assert_eq!(summary.successful_downloads, 1);
+    assert_eq!(summary.failed_downloads, 1);
+    assert_eq!(summary.retry_count, 1);
+    assert_eq!(summary.total_bytes, 3_000);
+    assert!(summary.average_speed > 0);
+}
+
+#[tokio::test]
+async fn test_shared_stats_count_only_running_time() {
+    let server = TestServer::start().await;
+    server.route("/a.bin", Route::new(sample_body(2_000)).delay(Duration::from_millis(300)));
+    server.route("/b.bin", Route::new(sample_body(3_000)).delay(Duration::from_millis(300)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let stats = Arc::new(DownloadStats::default());
+    let downloader = Downloader::builder()
+        .with_config(Config::new().with_download_dir(temp_dir.path()))
+        .with_stats(stats.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let first = downloader.download_many([server.url("/a.bin")]).await.unwrap().summary;
+    assert!(first.elapsed >= Duration::from_millis(300));
+    // 两次运行之间的空闲时间不计入耗时
+    tokio::time::sleep(Duration::from_millis(1_500)).await;
+    let second = downloader.download_many([server.url("/b.bin")]).await.unwrap().summary;
+
+    assert_eq!(second.successful_downloads, 2);
+    assert_eq!(second.total_bytes, 5_000);
+    assert!(second.elapsed >= first.elapsed + Duration::from_millis(300), "{:?}", second);
+    assert!(second.elapsed < first.elapsed + Duration::from_millis(1_500), "{:?}", second);
+    let expected = 5_000.0 / second.elapsed.as_secs_f64();
+    assert!((second.average_speed as f64 - expected).abs() < expected * 0.05, "{:?}", second);
+}
+
+#[tokio::test]
+async fn test_report_lists_every_url() {
+    let server = TestServer::start().await;
+    server.route("/one.bin", Route::new(sample_body(1_500)));
+    server.route("/two.bin", Route::new(sample_body(2_500)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 3,
+        urls: vec![
+            server.url("/one.bin"),
+            server.url("/gone.bin"),
+            server.url("/two.bin"),
+        ],
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(!report.is_success());
+
+    let urls: Vec<&str> = report.entries.iter().map(|e| e.url.as_str()).collect();
+    assert_eq!(urls, vec![server.url("/one.bin"), server.url("/gone.bin"), server.url("/two.bin")]);
+
+    let first = &report.entries[0];
+    assert!(matches!(first.outcome, DownloadOutcome::Completed));
+    assert_eq!(first.bytes, 1_500);
+    assert_eq!(first.path.as_deref(), Some(temp_dir.path().join("one.bin").as_path()));
+
+    match &report.entries[1].outcome {
+        DownloadOutcome::Failed(DownloadError::HttpError(_, status, _)) => assert_eq!(*status, 404),
+        other => panic!("Unexpected outcome: {:?}", other),
+    }
+    assert_eq!(report.entries[2].bytes, 2_500);
+    assert_eq!(report.completed().count(), 2);
+
+    let table = report.to_string();
+    assert!(table.contains("failed"));
+    assert!(table.contains("2 succeeded, 0 skipped, 1 failed"));
+}
+
+/// 模拟中断的下载：`.part` 文件加上记录远程版本的元数据文件
+fn seed_partial_download(config: &Config, url: &str, partial: &[u8], etag: &str, total: u64) {
+    let file_path = config.download_dir.join(url.rsplit('/').next().unwrap());
+    let part_path = part_file_path(&file_path);
+    std::fs::write(&part_path, partial).unwrap();
+
+    let meta = multhreadown::cache::DownloadCache {
+        url: url.to_string(),
+        file_size: total,
+        downloaded_size: partial.len() as u64,
+        etag: Some(etag.to_string()),
+        last_modified: None,
+        checksum: None,
+        path: None,
+    };
+    std::fs::write(part_meta_path(&part_path), serde_json::to_vec(&meta).unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_resume_sends_if_range() {
+    let server = TestServer::start().await;
+    let body = sample_body(6_000);
+    server.route("/resume.bin", Route::ranged(body.clone()).header("ETag", "\"v1\""));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/resume.bin")],
+        ..Config::default()
+    };
+    seed_partial_download(&config, &server.url("/resume.bin"), &body[..2_500], "\"v1\"", 6_000);
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(report.entries[0].bytes, 3_500);
+    assert_eq!(std::fs::read(temp_dir.path().join("resume.bin")).unwrap(), body);
+
+    let gets = server.requests_for("GET", "/resume.bin");
+    assert_eq!(gets.len(), 1);
+    assert_eq!(gets[0].header("range"), Some("bytes=2500-"));
+    assert_eq!(gets[0].header("if-range"), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn test_resume_restarts_when_remote_changed() {
+    let server = TestServer::start().await;
+    let body = sample_body(6_000);
+    server.route("/changed.bin", Route::ranged(body.clone()).header("ETag", "\"v2\""));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/changed.bin")],
+        ..Config::default()
+    };
+    seed_partial_download(&config, &server.url("/changed.bin"), &[0xff; 2_500], "\"v1\"", 6_000);
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("changed.bin")).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_complete_file_is_skipped() {
+    let server = TestServer::start().await;
+    let body = sample_body(4_000);
+    server.route("/done.bin", Route::ranged(body.clone()).header("ETag", "\"v1\""));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/done.bin")],
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Completed));
+
+    // 缓存记录显示文件已完整，不再发起请求
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Skipped(_)));
+    assert_eq!(report.summary.skipped_downloads, 1);
+    assert_eq!(server.requests_for("GET", "/done.bin").len(), 1);
+
+    // 没有缓存时与远程文件比较，大小一致的同名文件视为已完成
+    std::fs::remove_dir_all(config.cache_dir()).unwrap();
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Skipped(_)));
+    assert_eq!(std::fs::read(temp_dir.path().join("done.bin")).unwrap(), body);
+    assert_eq!(server.requests_for("GET", "/done.bin").len(), 1);
+
+    // 大小不符的同名文件（例如被截断）重新下载
+    std::fs::remove_dir_all(config.cache_dir()).unwrap();
+    std::fs::write(temp_dir.path().join("done.bin"), &body[..1_000]).unwrap();
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Completed), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("done.bin")).unwrap(), body);
+    assert_eq!(server.requests_for("GET", "/done.bin").len(), 2);
+}
+
+#[tokio::test]
+async fn test_interrupted_download_stays_in_part_file() {
+    let server = TestServer::start().await;
+    let body = sample_body(5_000);
+    server.route("/big.iso", Route::ranged(body.clone()).truncate_first(1));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/big.iso")],
+        segments: 1,
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let final_path = temp_dir.path().join("big.iso");
+    let part_path = part_file_path(&final_path);
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+    assert!(!final_path.exists(), "truncated data must not appear under the final name");
+    assert_eq!(std::fs::metadata(&part_path).unwrap().len(), 2_500);
+    assert!(part_meta_path(&part_path).exists());
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(&final_path).unwrap(), body);
+    assert!(!part_path.exists());
+    assert!(!part_meta_path(&part_path).exists());
+}
+
+#[tokio::test]
+async fn test_interrupted_segmented_download_resumes_segments() {
+    let server = TestServer::start().await;
+    let body = sample_body(64 * 1024);
+    server.route(
+        "/split.iso",
+        Route::ranged(body.clone())
+            .header("ETag", "\"v1\"")
+            .truncate_first(1),
+    );
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(2)
+        .with_chunk_size(1024)
+        .with_retry_attempts(0)
+        .with_urls([server.url("/split.iso")]);
+    let final_path = temp_dir.path().join("split.iso");
+    let part_path = part_file_path(&final_path);
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+    // 预分配的 `.part` 保留下来，进度记录在元数据中
+    assert_eq!(std::fs::metadata(&part_path).unwrap().len(), body.len() as u64);
+    let meta = std::fs::read(part_meta_path(&part_path)).unwrap();
+    let partials = maintenance::partial_downloads(&config).await.unwrap();
+    let downloaded = partials[0].downloaded.unwrap();
+    assert!((16_384..body.len() as u64).contains(&downloaded), "{}", downloaded);
+    assert_eq!(partials[0].total, body.len() as u64);
+
+    // 缺少元数据时进度未知
+    std::fs::remove_file(part_meta_path(&part_path)).unwrap();
+    let partials = maintenance::partial_downloads(&config).await.unwrap();
+    assert_eq!(partials[0].downloaded, None);
+    std::fs::write(part_meta_path(&part_path), meta).unwrap();
+
+    let before = server.requests_for("GET", "/split.iso").len();
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(&final_path).unwrap(), body);
+    assert!(!part_meta_path(&part_path).exists());
+
+    // 只请求各分段缺少的部分，并用 If-Range 确认远程文件没有变化
+    let resumed = &server.requests_for("GET", "/split.iso")[before..];
+    assert!(!resumed.is_empty() && resumed.len() <= 2);
+    assert!(resumed.iter().all(|r| r.header("if-range") == Some("\"v1\"")));
+    let ranges: Vec<_> = resumed.iter().filter_map(|r| r.header("range")).collect();
+    assert!(
+        ranges.contains(&"bytes=16384-32767") || ranges.contains(&"bytes=49152-65535"),
+        "{:?}",
+        ranges
+    );
+
+    // 没有元数据的 `.part` 不可信，丢弃后仍按分段下载
+    std::fs::remove_file(&final_path).unwrap();
+    std::fs::write(&part_path, vec![0u8; 1_000]).unwrap();
+    let before = server.requests_for("GET", "/split.iso").len();
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(&final_path).unwrap(), body);
+    let mut ranges: Vec<_> = server.requests_for("GET", "/split.iso")[before..]
+        .iter()
+        .filter_map(|r| r.header("range").map(str::to_string))
+        .collect();
+    ranges.sort();
+    assert_eq!(ranges, ["bytes=0-32767", "bytes=32768-65535"]);
+}
+
+#[tokio::test]
+async fn test_segmented_download_restarts_when_remote_changes() {
+    let server = TestServer::start().await;
+    let old = sample_body(64 * 1024);
+    let new: Vec<u8> = old.iter().rev().copied().collect();
+    // 探测之后远程文件就被替换了，分段请求的 If-Range 不再匹配
+    server.route(
+        "/moving.bin",
+        Route::ranged(old)
+            .header("ETag", "\"v1\"")
+            .replaced_after(1, Route::ranged(new.clone()).header("ETag", "\"v2\"")),
+    );
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(2)
+        .with_chunk_size(1024)
+        .with_retry_delay(Duration::ZERO)
+        .with_urls([server.url("/moving.bin")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("moving.bin")).unwrap(), new);
+
+    // 重新探测后按新版本分段下载
+    assert_eq!(server.requests_for("HEAD", "/moving.bin").len(), 2);
+    let gets = server.requests_for("GET", "/moving.bin");
+    assert!(gets
+        .iter()
+        .rev()
+        .take(2)
+        .all(|r| r.header("if-range") == Some("\"v2\"")));
+}
+
+fn integrity_check(url: &str, algorithm: ChecksumAlgorithm, expected: String) -> IntegrityCheck {
+    IntegrityCheck {
+        enabled: true,
+        algorithm,
+        checksums: HashMap::from([(url.to_string(), expected)]),
+        on_mismatch: MismatchPolicy::default(),
+    }
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    use sha2::Digest;
+    sha2::Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_checksum_verified_across_resume() {
+    let server = TestServer::start().await;
+    let body = sample_body(6_000);
+    server.route("/verified.bin", Route::ranged(body.clone()).header("ETag", "\"v1\""));
+    let url = server.url("/verified.bin");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![url.clone()],
+        integrity_check: Some(integrity_check(
+            &url,
+            ChecksumAlgorithm::SHA256,
+            sha256_hex(&body).to_uppercase(),
+        )),
+        ..Config::default()
+    };
+    seed_partial_download(&config, &url, &body[..2_500], "\"v1\"", 6_000);
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("verified.bin")).unwrap(), body);
+
+    let cache = CacheManager::new(config.cache_dir()).await.unwrap();
+    let entry = cache.get_cache(&url).unwrap();
+    let expected = format!("sha256:{}", sha256_hex(&body));
+    assert_eq!(entry.checksum.as_deref(), Some(expected.as_str()));
+}
+
+#[tokio::test]
+async fn test_segmented_download_is_verified() {
+    let server = TestServer::start().await;
+    let body = sample_body(10_000);
+    server.route("/segments.bin", Route::ranged(body.clone()));
+    let url = server.url("/segments.bin");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![url.clone()],
+        segments: 4,
+        min_segment_size: 1024,
+        integrity_check: Some(integrity_check(
+            &url,
+            ChecksumAlgorithm::MD5,
+            format!("{:x}", md5::compute(&body)),
+        )),
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+}
+
+#[tokio::test]
+async fn test_checksum_mismatch_fails_download() {
+    let server = TestServer::start().await;
+    server.route("/corrupt.bin", Route::new(sample_body(3_000)));
+    server.route("/kept.bin", Route::new(sample_body(3_000)));
+    let url = server.url("/corrupt.bin");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mut check = integrity_check(&url, ChecksumAlgorithm::SHA512, "00".repeat(64));
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![url.clone(), server.url("/kept.bin")],
+        integrity_check: Some(check.clone()),
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+    assert!(matches!(
+        report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::ChecksumMismatch(0, _, _))
+    ));
+    // 没有配置期望摘要的 URL 不受影响
+    assert!(matches!(report.entries[1].outcome, DownloadOutcome::Completed));
+
+    let final_path = temp_dir.path().join("corrupt.bin");
+    assert!(!final_path.exists());
+    assert!(!part_file_path(&final_path).exists());
+
+    // 隔离模式保留损坏的文件以便排查
+    check.on_mismatch = MismatchPolicy::Quarantine;
+    let config = Config {
+        integrity_check: Some(check),
+        ..config
+    };
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+    assert!(!final_path.exists());
+    assert!(config.quarantine_dir().join("corrupt.bin").exists());
+}
+
+#[tokio::test]
+async fn test_filter_skips_by_name_without_requests() {
+    let server = TestServer::start().await;
+    for path in ["/notes.md", "/setup.exe", "/data.bin"] {
+        server.route(path, Route::new(sample_body(1_000)));
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![
+            server.url("/notes.md"),
+            server.url("/setup.exe"),
+            server.url("/data.bin"),
+        ],
+        filter: Some(DownloadFilter {
+            include_patterns: vec!["*.md".to_string(), "*.exe".to_string()],
+            exclude_patterns: vec!["*.exe".to_string()],
+            ..DownloadFilter::default()
+        }),
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Completed));
+    assert!(matches!(
+        &report.entries[1].outcome,
+        DownloadOutcome::Skipped(reason) if reason.contains("*.exe")
+    ));
+    assert!(matches!(report.entries[2].outcome, DownloadOutcome::Skipped(_)));
+    assert_eq!(report.summary.skipped_downloads, 2);
+
+    let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+    assert!(paths.iter().all(|p| p == "/notes.md"), "{:?}", paths);
+}
+
+#[tokio::test]
+async fn test_filter_skips_by_size() {
+    let server = TestServer::start().await;
+    server.route("/small.bin", Route::new(sample_body(500)));
+    server.route("/large.bin", Route::new(sample_body(8_000)));
+    server.route("/streamed.bin", Route::new(sample_body(8_000)).unknown_length());
+    server.route("/fits.bin", Route::new(sample_body(2_000)).unknown_length());
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![
+            server.url("/small.bin"),
+            server.url("/large.bin"),
+            server.url("/streamed.bin"),
+            server.url("/fits.bin"),
+        ],
+        filter: Some(DownloadFilter {
+            min_size: Some(1_000),
+            max_size: Some(4_000),
+            ..DownloadFilter::default()
+        }),
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    for entry in &report.entries[..3] {
+        assert!(matches!(entry.outcome, DownloadOutcome::Skipped(_)), "{}", report);
+    }
+    assert!(matches!(report.entries[3].outcome, DownloadOutcome::Completed));
+
+    // HEAD 已给出大小时不再发起 GET
+    assert!(server.requests_for("GET", "/small.bin").is_empty());
+    assert!(server.requests_for("GET", "/large.bin").is_empty());
+    // 大小未知时在下载过程中超限被中止，不留下残余文件
+    let streamed = temp_dir.path().join("streamed.bin");
+    assert!(!streamed.exists());
+    assert!(!part_file_path(&streamed).exists());
+    assert_eq!(std::fs::read(temp_dir.path().join("fits.bin")).unwrap().len(), 2_000);
+}
+
+#[tokio::test]
+async fn test_pause_and_resume_continue_with_range() {
+    let server = TestServer::start().await;
+    let body = sample_body(64 * 1024);
+    server.route("/paused.bin", Route::ranged(body.clone()).header("ETag", "\"v1\""));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/paused.bin")],
+        ..Config::default()
+    };
+    let part_path = part_file_path(&temp_dir.path().join("paused.bin"));
+
+    // 限速保证暂停时下载尚未完成
+    let control = DownloadControl::new(Some(16));
+    let (status_tx, mut status_rx) = tokio::sync::mpsc::channel(16);
+    control.set_status_sender(status_tx);
+    let handle = tokio::spawn(downloader::download_all_files_with_control(
+        config,
+        control.clone(),
+    ));
+
+    let len = || std::fs::metadata(&part_path).map(|m| m.len()).unwrap_or(0);
+    while len() == 0 {
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    control.pause();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let paused_len = len();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(len(), paused_len, "paused download must not write");
+    assert!(paused_len < body.len() as u64);
+
+    control.limiter().set_rate_limit(None);
+    control.resume();
+    let report = handle.await.unwrap().unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("paused.bin")).unwrap(), body);
+
+    let gets = server.requests_for("GET", "/paused.bin");
+    assert_eq!(gets.len(), 2);
+    assert_eq!(gets[1].header("range"), Some(format!("bytes={}-", paused_len).as_str()));
+    assert_eq!(gets[1].header("if-range"), Some("\"v1\""));
+
+    let mut statuses = Vec::new();
+    while let Ok(status) = status_rx.try_recv() {
+        statuses.push(status);
+    }
+    assert!(matches!(
+        statuses.as_slice(),
+        [DownloadStatus::Paused, DownloadStatus::Running, DownloadStatus::Completed]
+    ), "{:?}", statuses);
+}
+
+#[tokio::test]
+async fn test_cancel_single_file() {
+    let server = TestServer::start().await;
+    server.route("/keep.bin", Route::new(sample_body(1_000)));
+    server.route("/drop.bin", Route::new(sample_body(1_000)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/keep.bin"), server.url("/drop.bin")],
+        ..Config::default()
+    };
+
+    let control = DownloadControl::default();
+    control.apply(Command::CancelFile(1));
+    let report = downloader::download_all_files_with_control(config, control)
+        .await
+        .unwrap();
+
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Completed));
+    assert!(matches!(
+        report.entries[1].outcome,
+        DownloadOutcome::Failed(DownloadError::Cancelled(1))
+    ));
+    assert!(server.requests_for("GET", "/drop.bin").is_empty());
+}
+
+#[test]
+fn test_config_builder_methods() {
+    let config = Config::new()
+        .with_download_dir("/tmp/downloads")
+        .with_urls(["https://example.com/a.zip"])
+        .with_threads(8)
+        .with_chunk_size(2 * 1024 * 1024)
+        .with_retry_attempts(5)
+        .with_retry_delay(std::time::Duration::from_secs(60))
+        .with_rate_limit(Some(1024))
+        .with_user_agent("MyDownloader/1.0");
+
+    assert_eq!(config.download_dir, std::path::PathBuf::from("/tmp/downloads"));
+    assert_eq!(config.urls, vec!["https://example.com/a.zip".to_string()]);
+    assert_eq!(config.workers, 8);
+    assert_eq!(config.min_segment_size, 2 * 1024 * 1024);
+    assert_eq!(config.retry.max_retries, 5);
+    assert_eq!(config.retry.initial_delay, 60);
+    assert!(config.retry.max_delay >= 60, "delay bounds must stay valid");
+    assert_eq!(config.rate_limit_kb, Some(1024));
+    assert_eq!(config.user_agent.as_deref(), Some("MyDownloader/1.0"));
+    assert!(config.validate().is_ok());
+}
+
+#[tokio::test]
+async fn test_downloader_is_reusable() {
+    let server = TestServer::start().await;
+    for path in ["/one.bin", "/two.bin", "/three.bin"] {
+        server.route(path, Route::new(sample_body(1_500)));
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let handler = Arc::new(TestEventHandler::default());
+    let downloader = Downloader::builder()
+        .with_config(
+            Config::new()
+                .with_download_dir(temp_dir.path())
+                .with_threads(2)
+                .with_user_agent("multhreadown-tests"),
+        )
+        .with_event_handler(handler.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let entry = downloader.download(server.url("/one.bin")).await.unwrap();
+    assert!(matches!(entry.outcome, DownloadOutcome::Completed));
+    assert_eq!(entry.path, Some(temp_dir.path().join("one.bin")));
+
+    let report = downloader
+        .download_many([server.url("/two.bin"), server.url("/three.bin")])
+        .await
+        .unwrap();
+    assert!(report.is_success(), "{}", report);
+    // 每次运行的汇总只统计本次的 URL
+    assert_eq!(report.summary.successful_downloads, 2);
+    assert_eq!(handler.complete_count.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+    for request in server.requests() {
+        assert_eq!(request.header("user-agent"), Some("multhreadown-tests"));
+    }
+}
+
+#[tokio::test]
+async fn test_downloader_is_reusable_after_cancel() {
+    let server = TestServer::start().await;
+    server.route("/slow.bin", Route::new(sample_body(1_500)).delay(Duration::from_millis(300)));
+    server.route("/one.bin", Route::new(sample_body(1_500)));
+    server.route("/two.bin", Route::new(sample_body(1_500)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let downloader = Arc::new(
+        Downloader::builder()
+            .with_config(Config::new().with_download_dir(temp_dir.path()))
+            .build()
+            .await
+            .unwrap(),
+    );
+
+    // 运行中的取消只作用于该次运行，之后的下载不受影响
+    let start_slow = || {
+        let downloader = downloader.clone();
+        let url = server.url("/slow.bin");
+        tokio::spawn(async move { downloader.download(url).await.unwrap() })
+    };
+    let run = start_slow();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    downloader.control().cancel();
+    let entry = run.await.unwrap();
+    assert!(matches!(entry.outcome, DownloadOutcome::Failed(DownloadError::Cancelled(0))));
+    let entry = downloader.download(server.url("/one.bin")).await.unwrap();
+    assert!(matches!(entry.outcome, DownloadOutcome::Completed), "{:?}", entry.outcome);
+
+    // 单个 URL 的下载总是序号 0，取消它也不会影响之后的单个下载
+    let run = start_slow();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    downloader.control().cancel_file(0);
+    let entry = run.await.unwrap();
+    assert!(matches!(entry.outcome, DownloadOutcome::Failed(DownloadError::Cancelled(0))));
+    let entry = downloader.download(server.url("/two.bin")).await.unwrap();
+    assert!(matches!(entry.outcome, DownloadOutcome::Completed), "{:?}", entry.outcome);
+    assert!(!downloader.control().is_cancelled(0));
+}
+
+#[tokio::test]
+async fn test_status_lists_partial_downloads() {
+    let server = TestServer::start().await;
+    server.route("/half.iso", Route::ranged(sample_body(4_000)).truncate_first(1));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/half.iso")],
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+
+    let partials = maintenance::partial_downloads(&config).await.unwrap();
+    assert_eq!(partials.len(), 1);
+    assert_eq!(partials[0].url, server.url("/half.iso"));
+    assert_eq!(partials[0].path, temp_dir.path().join("half.iso"));
+    assert_eq!(partials[0].downloaded, Some(2_000));
+    assert_eq!(partials[0].total, 4_000);
+}
+
+#[tokio::test]
+async fn test_verify_detects_modified_files() {
+    let server = TestServer::start().await;
+    let body = sample_body(3_000);
+    server.route("/checked.bin", Route::new(body.clone()));
+    server.route("/unchecked.bin", Route::new(body.clone()));
+    let url = server.url("/checked.bin");
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![url.clone(), server.url("/unchecked.bin")],
+        integrity_check: Some(integrity_check(
+            &url,
+            ChecksumAlgorithm::SHA256,
+            sha256_hex(&body),
+        )),
+        ..Config::default()
+    };
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    // 下载时记录的摘要足以重新校验，不再需要配置
+    let config = Config {
+        integrity_check: None,
+        ..config
+    };
+    let results = maintenance::verify_downloads(&config).await.unwrap();
+    let statuses: Vec<_> = results.iter().map(|r| r.status.clone()).collect();
+    assert_eq!(statuses, vec![VerifyStatus::Ok, VerifyStatus::Unverifiable]);
+
+    std::fs::write(temp_dir.path().join("checked.bin"), b"tampered").unwrap();
+    let results = maintenance::verify_downloads(&config).await.unwrap();
+    assert!(matches!(results[0].status, VerifyStatus::Mismatch { .. }));
+
+    std::fs::remove_file(temp_dir.path().join("checked.bin")).unwrap();
+    let results = maintenance::verify_downloads(&config).await.unwrap();
+    assert_eq!(results[0].status, VerifyStatus::Missing);
+}
+
+#[tokio::test]
+async fn test_clean_removes_stale_leftovers() {
+    let server = TestServer::start().await;
+    server.route("/resumable.bin", Route::ranged(sample_body(4_000)).truncate_first(1));
+    server.route("/gone.bin", Route::new(sample_body(1_000)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 1,
+        urls: vec![server.url("/resumable.bin"), server.url("/gone.bin")],
+        retry: RetryConfig {
+            max_retries: 0,
+            ..RetryConfig::default()
+        },
+        ..Config::default()
+    };
+    downloader::download_all_files(config.clone()).await.unwrap();
+
+    let resumable = part_file_path(&temp_dir.path().join("resumable.bin"));
+    let orphan = temp_dir.path().join("nested").join("orphan.bin.part");
+    std::fs::create_dir_all(orphan.parent().unwrap()).unwrap();
+    std::fs::write(&orphan, b"no metadata").unwrap();
+    let leftover = part_file_path(&temp_dir.path().join("gone.bin"));
+    std::fs::write(&leftover, b"no metadata").unwrap();
+    std::fs::remove_file(temp_dir.path().join("gone.bin")).unwrap();
+
+    // 只删除缓存记录对应的 `.part`，来历不明的 `.part` 保留
+    let summary = maintenance::clean(&config, false).await.unwrap();
+    assert_eq!(summary.removed_files, vec![leftover.clone()]);
+    assert_eq!(summary.removed_entries, vec![server.url("/gone.bin")]);
+    assert!(resumable.exists());
+    assert!(orphan.exists());
+    assert_eq!(maintenance::partial_downloads(&config).await.unwrap().len(), 1);
+
+    let summary = maintenance::clean(&config, true).await.unwrap();
+    assert!(summary.removed_files.contains(&resumable));
+    assert!(summary.removed_files.contains(&part_meta_path(&resumable)));
+    assert!(summary.removed_files.contains(&orphan));
+    assert!(maintenance::partial_downloads(&config).await.unwrap().is_empty());
+}
+
+#[test]
+fn test_example_config_is_valid() {
+    let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("examples/config.toml");
+    let config = Config::from_file(&path).unwrap();
+    config.validate().unwrap();
+    // 示例中的摘要是占位值，开启校验会把下载的文件当作损坏删除
+    assert!(config.integrity_check.is_some_and(|check| !check.enabled));
+}
+
+#[test]
+fn test_config_layers_precedence() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let system = temp_dir.path().join("system.toml");
+    let user = temp_dir.path().join("user.toml");
+    std::fs::write(&system, "workers = 2\nrate_limit_kb = 100\n[retry]\nmax_retries = 9\n").unwrap();
+    std::fs::write(&user, "workers = 3\n[retry]\ninitial_delay = 5\nmax_delay = 60\n").unwrap();
+
+    let env = [
+        ("MULTHREADOWN_WORKERS", "6"),
+        ("MULTHREADOWN_RANDOM_ORDER", "true"),
+        ("MULTHREADOWN_RETRY__BACKOFF_FACTOR", "3"),
+        ("MULTHREADOWN_URLS", "https://example.com/a, https://example.com/b"),
+        ("MULTHREADOWN_USER_AGENT", "ci-bot/1.0"),
+        ("UNRELATED", "ignored"),
+    ]
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+
+    let mut cli = toml::Table::new();
+    cli.insert("rate_limit_kb".into(), toml::Value::Integer(50));
+
+    let config = ConfigLayers::new()
+        .optional_file(Some(&system))
+        .unwrap()
+        .optional_file(Some(temp_dir.path().join("missing.toml")))
+        .unwrap()
+        .file(&user)
+        .unwrap()
+        .env(env)
+        .unwrap()
+        .overrides(cli)
+        .build()
+        .unwrap();
+
+    assert_eq!(config.workers, 6);
+    assert!(config.random_order);
+    assert_eq!(config.rate_limit_kb, Some(50));
+    // 嵌套的表按键合并
+    assert_eq!(config.retry.max_retries, 9);
+    assert_eq!(config.retry.initial_delay, 5);
+    assert_eq!(config.retry.backoff_factor, 3.0);
+    assert_eq!(config.urls, vec!["https://example.com/a", "https://example.com/b"]);
+    assert_eq!(config.user_agent.as_deref(), Some("ci-bot/1.0"));
+    // 未被任何一层设置的值保持默认
+    assert_eq!(config.segments, Config::default().segments);
+}
+
+#[test]
+fn test_config_env_types_unset_settings() {
+    // 未设置的可选项按字段类型解析，而不是按值的样子猜
+    let env = [
+        ("MULTHREADOWN_USER_AGENT", "1.0"),
+        ("MULTHREADOWN_AUTH__HOSTS", "example.com"),
+        ("MULTHREADOWN_AUTH__USERNAME", "007"),
+        ("MULTHREADOWN_AUTH__PASSWORD", "12345"),
+        ("MULTHREADOWN_AUTH__BEARER_TOKEN", "12345"),
+        ("MULTHREADOWN_TLS__MIN_VERSION", "1.2"),
+        ("MULTHREADOWN_FILE_TIMEOUT", "30"),
+        ("MULTHREADOWN_FILTER__EXCLUDE_PATTERNS", "*.tmp, *.bak"),
+    ]
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+
+    let config = ConfigLayers::new().env(env).unwrap().build().unwrap();
+    assert_eq!(config.user_agent.as_deref(), Some("1.0"));
+    let auth = config.auth.unwrap();
+    assert_eq!(auth.hosts, vec!["example.com"]);
+    assert_eq!(auth.username.as_deref(), Some("007"));
+    assert_eq!(auth.password.as_deref(), Some("12345"));
+    assert_eq!(auth.bearer_token.as_deref(), Some("12345"));
+    assert_eq!(config.tls.unwrap().min_version.as_deref(), Some("1.2"));
+    assert_eq!(config.file_timeout, Some(30));
+    assert_eq!(config.filter.unwrap().exclude_patterns, vec!["*.tmp", "*.bak"]);
+}
+
+#[test]
+fn test_config_layers_reject_bad_values() {
+    let bad_type = ConfigLayers::new().env([("MULTHREADOWN_WORKERS".to_string(), "many".to_string())]);
+    assert!(matches!(bad_type, Err(ConfigError::InvalidEnv(..))));
+
+    let unknown = ConfigLayers::new()
+        .env([("MULTHREADOWN_WROKERS".to_string(), "4".to_string())])
+        .unwrap()
+        .build();
+    assert!(matches!(unknown, Err(ConfigError::Invalid(_))));
+}
+
+#[tokio::test]
+async fn test_input_file_parsing() {
+    let text = "\
+# mirror list
+https://example.com/a.iso
+  out=images/debian.iso
+\tdir=/srv/mirror
+  checksum=SHA-256=ABCDEF
+  split=4
+
+https://example.com/b.iso\thttps://mirror.example.com/b.iso
+  checksum=crc32=1234
+https://example.com/c.iso
+";
+    let entries: Vec<_> = input::read_requests(text.as_bytes()).collect().await;
+    assert_eq!(entries.len(), 3);
+
+    // 不支持的 aria2 选项（split）被忽略
+    assert_eq!(
+        entries[0].as_ref().unwrap(),
+        &DownloadRequest::new("https://example.com/a.iso")
+            .with_out("images/debian.iso")
+            .with_dir("/srv/mirror")
+            .with_checksum(ChecksumAlgorithm::SHA256, "abcdef")
+    );
+    // 只使用第一个镜像；无效选项使该条目失败，并带上所属 URL 和行号
+    match &entries[1] {
+        Err(e @ InputError::Invalid { line: 9, .. }) => {
+            assert_eq!(e.url(), Some("https://example.com/b.iso"));
+        }
+        other => panic!("expected invalid entry, got {:?}", other),
+    }
+    assert_eq!(
+        entries[2].as_ref().unwrap(),
+        &DownloadRequest::new("https://example.com/c.iso")
+    );
+
+    let stray: Vec<_> = input::read_requests("  out=x\n".as_bytes()).collect().await;
+    assert!(matches!(stray[..], [Err(InputError::Invalid { url: None, .. })]));
+}
+
+#[test]
+fn test_config_accepts_long_url_lists() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let urls: Vec<String> = (0..1_000)
+        .map(|i| format!("https://example.com/{}.deb", i))
+        .collect();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_urls(urls);
+    assert!(config.validate().is_ok());
+
+    // 从输入文件读取 URL 时配置中可以没有 URL
+    let config = Config::new().with_download_dir(temp_dir.path());
+    assert!(matches!(config.validate(), Err(ConfigError::NoUrls)));
+    assert!(config.validate_settings().is_ok());
+}
+
+#[tokio::test]
+async fn test_download_requests_from_input() {
+    let server = TestServer::start().await;
+    let body = sample_body(3_000);
+    for i in 0..20 {
+        server.route(&format!("/pkg/{}.deb", i), Route::new(body.clone()));
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let mirror_dir = temp_dir.path().join("mirror");
+    let mut text = format!(
+        "{}\n  out=renamed/first.deb\n  checksum=sha-256={}\n{}\n  dir={}\n  checksum=md5=00\n",
+        server.url("/pkg/0.deb"),
+        sha256_hex(&body),
+        server.url("/pkg/1.deb"),
+        mirror_dir.display()
+    );
+    for i in 2..20 {
+        text.push_str(&server.url(&format!("/pkg/{}.deb", i)));
+        text.push('\n');
+    }
+
+    let downloader = Downloader::builder()
+        .with_config(
+            Config::new()
+                .with_download_dir(temp_dir.path().join("downloads"))
+                .with_cache_dir(temp_dir.path().join("cache"))
+                .with_threads(3),
+        )
+        .build()
+        .await
+        .unwrap();
+    let report = downloader
+        .download_requests(input::read_requests(text.as_bytes()))
+        .await
+        .unwrap();
+
+    assert_eq!(report.entries.len(), 20);
+    assert_eq!(report.summary.successful_downloads, 19);
+    let downloads = temp_dir.path().join("downloads");
+    assert_eq!(
+        report.entries[0].path,
+        Some(downloads.join("renamed/first.deb"))
+    );
+    assert_eq!(std::fs::read(downloads.join("renamed/first.deb")).unwrap(), body);
+    // 按 `dir` 保存，期望摘要不符时下载失败
+    assert_eq!(report.entries[1].path, Some(mirror_dir.join("1.deb")));
+    assert!(matches!(
+        report.entries[1].outcome,
+        DownloadOutcome::Failed(DownloadError::ChecksumMismatch(..))
+    ));
+    assert!(!mirror_dir.join("1.deb").exists());
+    for i in 2..20 {
+        assert!(downloads.join(format!("{}.deb", i)).exists());
+    }
+
+    // 缓存记录了实际保存路径，离线校验不需要原来的输入文件
+    let config = downloader.config().clone();
+    let results = maintenance::verify_downloads(&config).await.unwrap();
+    let first = results
+        .iter()
+        .find(|r| r.url == server.url("/pkg/0.deb"))
+        .unwrap();
+    assert_eq!(first.path, downloads.join("renamed/first.deb"));
+    assert_eq!(first.status, VerifyStatus::Ok);
+}
+
+#[test]
+fn test_filename_resolution_helpers() {
+    assert_eq!(
+        filename::from_content_disposition(r#"attachment; filename="report; final.pdf""#),
+        Some("report; final.pdf".to_string())
+    );
+    // `filename*` 优先，按 RFC 5987 解码
+    assert_eq!(
+        filename::from_content_disposition(
+            "attachment; filename=resume.pdf; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"
+        ),
+        Some("résumé.pdf".to_string())
+    );
+    // 服务器给出的路径只保留文件名部分
+    assert_eq!(
+        filename::from_content_disposition(r#"attachment; filename="../../etc/passwd""#),
+        Some("passwd".to_string())
+    );
+    assert_eq!(filename::from_content_disposition("inline"), None);
+
+    let url = |s: &str| reqwest::Url::parse(s).unwrap();
+    assert_eq!(
+        filename::from_url(&url("https://example.com/a%20b.tar.gz?token=abc#top")),
+        Some("a b.tar.gz".to_string())
+    );
+    assert_eq!(
+        filename::from_url(&url("https://example.com/releases/latest/")),
+        Some("latest".to_string())
+    );
+    assert_eq!(filename::from_url(&url("https://example.com/")), None);
+
+    assert_eq!(filename::sanitize("what?.txt"), Some("what_.txt".to_string()));
+    assert_eq!(filename::sanitize(".."), None);
+    assert_eq!(filename::sanitize(&"x".repeat(300)).unwrap().len(), 255);
+    assert_eq!(filename::numbered("data.tar.gz", 2), "data.tar (2).gz");
+    assert_eq!(filename::numbered(".bashrc", 1), ".bashrc (1)");
+}
+
+#[tokio::test]
+async fn test_filename_from_response_headers() {
+    let server = TestServer::start().await;
+    server.route(
+        "/download?id=7",
+        Route::new(sample_body(1_000))
+            .header("Content-Disposition", "attachment; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"),
+    );
+    server.route("/latest", Route::redirect("/files/tool-v2.tar.gz"));
+    server.route("/files/tool-v2.tar.gz", Route::new(sample_body(2_000)));
+    server.route("/docs/", Route::new(sample_body(300)));
+    server.route("/a%20b.txt?token=secret", Route::new(sample_body(400)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config {
+        download_dir: temp_dir.path().to_path_buf(),
+        workers: 2,
+        urls: vec![
+            server.url("/download?id=7"),
+            server.url("/latest"),
+            server.url("/docs/"),
+            server.url("/a%20b.txt?token=secret"),
+        ],
+        ..Config::default()
+    };
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    let names: Vec<_> = report
+        .entries
+        .iter()
+        .map(|e| e.path.as_ref().unwrap().file_name().unwrap().to_string_lossy().into_owned())
+        .collect();
+    assert_eq!(names, ["résumé.pdf", "tool-v2.tar.gz", "docs", "a b.txt"]);
+    assert_eq!(std::fs::read(temp_dir.path().join("tool-v2.tar.gz")).unwrap().len(), 2_000);
+
+    // 再次运行沿用缓存中记录的路径，不再探测
+    let heads = server.requests().iter().filter(|r| r.method == "HEAD").count();
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert_eq!(report.summary.skipped_downloads, 4);
+    assert_eq!(
+        server.requests().iter().filter(|r| r.method == "HEAD").count(),
+        heads
+    );
+}
+
+#[tokio::test]
+async fn test_filename_from_download_response() {
+    // 单连接下载不需要探测，文件名直接取自下载请求的响应，不额外发出 HEAD
+    let server = TestServer::start().await;
+    server.route(
+        "/download?id=7",
+        Route::new(sample_body(1_000)).header("Content-Disposition", "attachment; filename=\"report.pdf\""),
+    );
+    server.route("/latest", Route::redirect("/files/tool-v2.tar.gz"));
+    server.route("/files/tool-v2.tar.gz", Route::new(sample_body(2_000)));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(1)
+        .with_urls([server.url("/download?id=7"), server.url("/latest")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    assert_eq!(std::fs::read(temp_dir.path().join("report.pdf")).unwrap(), sample_body(1_000));
+    assert_eq!(std::fs::read(temp_dir.path().join("tool-v2.tar.gz")).unwrap(), sample_body(2_000));
+    assert!(server.requests().iter().all(|r| r.method == "GET"));
+    assert_eq!(server.requests_for("GET", "/download?id=7").len(), 1);
+    assert_eq!(server.requests_for("GET", "/files/tool-v2.tar.gz").len(), 1);
+}
+
+#[tokio::test]
+async fn test_collision_policies() {
+    let server = TestServer::start().await;
+    let (first, second) = (sample_body(1_000), sample_body(1_500));
+    server.route("/x/data.bin", Route::new(first.clone()));
+    server.route("/y/data.bin", Route::new(second.clone()));
+    let urls = vec![server.url("/x/data.bin"), server.url("/y/data.bin")];
+    let config_in = |dir: &std::path::Path, policy| Config {
+        download_dir: dir.to_path_buf(),
+        workers: 1,
+        urls: urls.clone(),
+        on_collision: policy,
+        ..Config::default()
+    };
+
+    // 默认改名保存，再次运行时两个文件都被识别为已完成
+    let dir = tempfile::tempdir().unwrap();
+    let config = config_in(dir.path(), CollisionPolicy::default());
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(dir.path().join("data.bin")).unwrap(), first);
+    assert_eq!(std::fs::read(dir.path().join("data (1).bin")).unwrap(), second);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert_eq!(report.summary.skipped_downloads, 2);
+    assert_eq!(server.requests_for("GET", "/y/data.bin").len(), 1);
+
+    let dir = tempfile::tempdir().unwrap();
+    let report = downloader::download_all_files(config_in(dir.path(), CollisionPolicy::Skip))
+        .await
+        .unwrap();
+    assert!(matches!(
+        &report.entries[1].outcome,
+        DownloadOutcome::Skipped(reason) if reason.contains("already exists")
+    ));
+    assert_eq!(std::fs::read(dir.path().join("data.bin")).unwrap(), first);
+
+    let dir = tempfile::tempdir().unwrap();
+    let report = downloader::download_all_files(config_in(dir.path(), CollisionPolicy::Error))
+        .await
+        .unwrap();
+    assert!(matches!(
+        report.entries[1].outcome,
+        DownloadOutcome::Failed(DownloadError::FileExists(_))
+    ));
+
+    // 覆盖只替换之前运行留下的文件
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = config_in(dir.path(), CollisionPolicy::Overwrite);
+    config.urls.truncate(1);
+    downloader::download_all_files(config.clone()).await.unwrap();
+    config.urls = vec![server.url("/y/data.bin")];
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(matches!(report.entries[0].outcome, DownloadOutcome::Completed));
+    assert_eq!(std::fs::read(dir.path().join("data.bin")).unwrap(), second);
+    assert!(!dir.path().join("data (1).bin").exists());
+}
+
+#[test]
+fn test_output_template_rendering() {
+    let url = reqwest::Url::parse("https://mirror.example.com/pub/linux%20iso/debian.iso?x=1").unwrap();
+    let render = |template: &str, index| {
+        OutputTemplate::parse(template).unwrap().render(&TemplateVars {
+            url: &url,
+            name: "debian.iso",
+            index,
+            date: "2024-05-01",
+        })
+    };
+
+    assert_eq!(
+        render("{host}/{path}", 0).unwrap(),
+        std::path::Path::new("mirror.example.com/pub/linux iso/debian.iso")
+    );
+    assert_eq!(render("{index:03}-{name}", 7).unwrap(), std::path::Path::new("007-debian.iso"));
+    assert_eq!(
+        render("{date}/{stem}.{index}.{ext}", 12).unwrap(),
+        std::path::Path::new("2024-05-01/debian.12.iso")
+    );
+
+    // 模板本身或 URL 中的 `..` 都不能跳出保存目录
+    assert!(render("../{name}", 0).is_err());
+    let escaping = reqwest::Url::parse("https://example.com/a/..%2F..%2Fetc/passwd").unwrap();
+    let vars = TemplateVars {
+        url: &escaping,
+        name: "passwd",
+        index: 0,
+        date: "2024-05-01",
+    };
+    assert!(OutputTemplate::parse("{path}").unwrap().render(&vars).is_err());
+
+    for bad in ["{nope}", "/abs/{name}", "{name", "{host:03}"] {
+        assert!(OutputTemplate::parse(bad).is_err(), "{}", bad);
+    }
+    let config = Config::new()
+        .with_urls(vec!["https://example.com/a".to_string()])
+        .with_output_template("{hots}/{name}");
+    assert!(matches!(config.validate(), Err(ConfigError::InvalidTemplate(_))));
+}
+
+#[tokio::test]
+async fn test_download_with_output_templates() {
+    let server = TestServer::start().await;
+    let body = sample_body(2_000);
+    server.route("/pub/a/data.bin", Route::new(body.clone()));
+    server.route("/pub/b/data.bin", Route::new(body.clone()));
+    server.route("/pub/evil", Route::new(body.clone()).header(
+        "Content-Disposition",
+        "attachment; filename=\"../../escape.bin\"",
+    ));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let downloads = temp_dir.path().join("downloads");
+    let downloader = Downloader::builder()
+        .with_config(
+            Config::new()
+                .with_download_dir(&downloads)
+                .with_cache_dir(temp_dir.path().join("cache"))
+                .with_output_template("{host}/{path}"),
+        )
+        .build()
+        .await
+        .unwrap();
+    let report = downloader
+        .download_many([
+            DownloadRequest::new(server.url("/pub/a/data.bin")),
+            DownloadRequest::new(server.url("/pub/b/data.bin")).with_template("{index:03}-{name}"),
+            DownloadRequest::new(server.url("/pub/evil")),
+        ])
+        .await
+        .unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let host = reqwest::Url::parse(&server.url("/")).unwrap().host_str().unwrap().to_string();
+    let mirrored = downloads.join(&host).join("pub/a/data.bin");
+    assert_eq!(report.entries[0].path, Some(mirrored.clone()));
+    assert_eq!(std::fs::read(&mirrored).unwrap(), body);
+    assert_eq!(std::fs::read(downloads.join("001-data.bin")).unwrap(), body);
+    // 远程给出的名称只保留最后一段
+    assert!(downloads.join(&host).join("pub/escape.bin").exists());
+    assert!(!temp_dir.path().join("escape.bin").exists());
+}
+
+#[tokio::test]
+async fn test_preflight_probes_before_downloading() {
+    let server = TestServer::start().await;
+    let (small, large) = (sample_body(4_000), sample_body(9_000));
+    server.route("/a.bin", Route::ranged(small.clone()));
+    server.route("/b.bin", Route::ranged(small.clone()).no_head());
+    server.route("/huge.bin", Route::new(large));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_urls([
+            server.url("/a.bin"),
+            server.url("/b.bin"),
+            server.url("/huge.bin"),
+        ])
+        .with_segments(2)
+        .with_chunk_size(1_000)
+        .with_filter(DownloadFilter {
+            max_size: Some(8_000),
+            ..DownloadFilter::default()
+        })
+        .with_preflight(true);
+    let report = downloader::download_all_files(config).await.unwrap();
+
+    assert_eq!(report.summary.successful_downloads, 2, "{}", report);
+    assert!(matches!(
+        &report.entries[2].outcome,
+        DownloadOutcome::Skipped(reason) if reason.contains("max_size")
+    ));
+    assert_eq!(std::fs::read(temp_dir.path().join("a.bin")).unwrap(), small);
+    assert_eq!(std::fs::read(temp_dir.path().join("b.bin")).unwrap(), small);
+
+    // 所有探测都在下载开始之前完成，下载时不再重复探测
+    let requests = server.requests();
+    let is_probe = |r: &&common::RecordedRequest| {
+        r.method == "HEAD" || r.header("range") == Some("bytes=0-0")
+    };
+    let first_download = requests.iter().position(|r| !is_probe(&r)).unwrap();
+    assert_eq!(requests.iter().filter(is_probe).count(), 4);
+    assert!(requests[first_download..].iter().all(|r| !is_probe(&r)));
+    assert_eq!(server.requests_for("HEAD", "/a.bin").len(), 1);
+    // 不接受 HEAD 的服务器改用一个字节的 Range 请求，仍然可以分段下载
+    assert!(server
+        .requests_for("GET", "/b.bin")
+        .iter()
+        .any(|r| r.header("range") == Some("bytes=0-0")));
+    assert!(server
+        .requests_for("GET", "/b.bin")
+        .iter()
+        .any(|r| r.header("range") == Some("bytes=2000-3999")));
+    assert!(server.requests_for("GET", "/huge.bin").is_empty());
+}
+
+#[tokio::test]
+async fn test_insufficient_disk_space_fails_early() {
+    // 声称 1 PiB，实际只发送很少的内容
+    let server = TestServer::start().await;
+    server.route("/huge.iso", Route::new(sample_body(100)).claim_length(1 << 50));
+    server.route("/small.bin", Route::new(sample_body(100)));
+    let urls = vec![server.url("/huge.iso"), server.url("/small.bin")];
+
+    // 预先探测时在发出任何 GET 之前失败
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_urls(urls.clone())
+        .with_preflight(true);
+    let result = downloader::download_all_files(config).await;
+    assert!(matches!(
+        result,
+        Err(DownloadError::InsufficientSpace { needed, .. }) if needed == (1 << 50) + 100
+    ));
+    assert!(server.requests().iter().all(|r| r.method == "HEAD"));
+
+    // 没有预先探测时在收到响应头后失败，不留下 `.part` 文件，其他文件不受影响
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_urls(urls.clone());
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(matches!(
+        report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::InsufficientSpace { .. })
+    ));
+    assert!(matches!(report.entries[1].outcome, DownloadOutcome::Completed));
+    assert!(!part_file_path(&temp_dir.path().join("huge.iso")).exists());
+
+    // 关闭检查后照常开始下载
+    let config = config.with_disk_space_check(false).with_retry_attempts(0);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(!matches!(
+        report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::InsufficientSpace { .. })
+    ));
+}
+
+#[tokio::test]
+async fn test_concurrent_downloads_reserve_disk_space() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    // 每个文件单独都放得下，三个加起来放不下；前两个停在半途，一直占着预留的空间
+    let claimed = fs2::available_space(temp_dir.path()).unwrap() * 2 / 5;
+    let server = TestServer::start().await;
+    let urls: Vec<_> = ["/a.iso", "/b.iso", "/c.iso"]
+        .into_iter()
+        .map(|path| {
+            server.route(
+                path,
+                Route::new(sample_body(1_000))
+                    .claim_length(claimed)
+                    .stall_first(usize::MAX),
+            );
+            server.url(path)
+        })
+        .collect();
+
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_threads(3)
+        .with_segments(1)
+        .with_retry_attempts(0)
+        .with_read_timeout(Duration::from_secs(1))
+        .with_urls(urls);
+    let report = downloader::download_all_files(config).await.unwrap();
+    let out_of_space = report
+        .entries
+        .iter()
+        .filter(|entry| {
+            matches!(
+                entry.outcome,
+                DownloadOutcome::Failed(DownloadError::InsufficientSpace { .. })
+            )
+        })
+        .count();
+    assert_eq!(out_of_space, 1, "{}", report);
+}
+
+#[tokio::test]
+async fn test_preallocated_segmented_download() {
+    let server = TestServer::start().await;
+    let body = sample_body(64 * 1024);
+    server.route("/seg.bin", Route::ranged(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_urls([server.url("/seg.bin")])
+        .with_segments(4)
+        .with_chunk_size(8 * 1024)
+        .with_preallocation(true);
+    let report = downloader::download_all_files(config).await.unwrap();
+
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("seg.bin")).unwrap(), body);
+    assert_eq!(
+        server
+            .requests_for("GET", "/seg.bin")
+            .iter()
+            .filter(|r| r.header("range").is_some())
+            .count(),
+        4
+    );
+}
+
+#[tokio::test]
+async fn test_preallocated_single_stream_download_resumes() {
+    let server = TestServer::start().await;
+    let body = sample_body(40_000);
+    server.route(
+        "/single.bin",
+        Route::ranged(body.clone())
+            .header("ETag", "\"v1\"")
+            .truncate_first(1),
+    );
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(1)
+        .with_retry_attempts(0)
+        .with_preallocation(true)
+        .with_urls([server.url("/single.bin")]);
+    let final_path = temp_dir.path().join("single.bin");
+    let part_path = part_file_path(&final_path);
+
+    let report = downloader::download_all_files(config.clone()).await.unwrap();
+    assert!(!report.is_success());
+    // `.part` 已预分配为完整大小，续传的起点记录在元数据中
+    assert_eq!(std::fs::metadata(&part_path).unwrap().len(), body.len() as u64);
+    let partials = maintenance::partial_downloads(&config).await.unwrap();
+    assert_eq!(partials[0].downloaded, Some(20_000));
+
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(&final_path).unwrap(), body);
+    let resumed = server.requests_for("GET", "/single.bin").pop().unwrap();
+    assert_eq!(resumed.header("range"), Some("bytes=20000-"));
+    assert_eq!(resumed.header("if-range"), Some("\"v1\""));
+}
+
+#[test]
+fn test_netrc_and_authorization() {
+    let netrc = Netrc::parse(
+        "machine files.example.com login alice password s3cret\n\
+         # 注释\n\
+         macdef init\n\
+         cd /pub\n\
+         \n\
+         default login anonymous password guest\n",
+    )
+    .unwrap();
+    assert_eq!(
+        netrc.get("files.example.com").unwrap().login.as_deref(),
+        Some("alice")
+    );
+    assert_eq!(netrc.get("other.example.com").unwrap().login.as_deref(), Some("anonymous"));
+    assert!(Netrc::parse("login alice").is_err());
+
+    let url = |s: &str| reqwest::Url::parse(s).unwrap();
+    let header = |auth: Option<&AuthConfig>, netrc: Option<&Netrc>, u: &str| {
+        auth::authorization(auth, netrc, &url(u)).map(|v| v.to_str().unwrap().to_string())
+    };
+    let bearer = AuthConfig::bearer("tok").for_hosts(["artifacts.example.com"]);
+    assert_eq!(
+        header(Some(&bearer), Some(&netrc), "https://artifacts.example.com/a").as_deref(),
+        Some("Bearer tok")
+    );
+    // 其他主机不会收到令牌，改用 `.netrc`
+    assert_eq!(
+        header(Some(&bearer), Some(&netrc), "https://files.example.com/a").as_deref(),
+        Some("Basic YWxpY2U6czNjcmV0")
+    );
+    assert_eq!(header(Some(&bearer), None, "https://files.example.com/a"), None);
+
+    // 凭据不会出现在输出的配置中
+    let config = Config::new().with_auth(AuthConfig::basic("bob", "hunter2"));
+    let printed = toml::to_string(&config).unwrap();
+    assert!(printed.contains("bob") && !printed.contains("hunter2"));
+    assert!(matches!(
+        Config::new().with_header("Bad Name", "x").validate_settings(),
+        Err(ConfigError::InvalidHeader(_))
+    ));
+
+    // 没有指定主机的凭据不会发给任何主机，配置也无法通过校验
+    let unscoped = AuthConfig::bearer("tok");
+    assert_eq!(header(Some(&unscoped), None, "https://artifacts.example.com/a"), None);
+    assert!(matches!(
+        config.clone().validate_settings(),
+        Err(ConfigError::InvalidAuth(_))
+    ));
+    assert!(config
+        .with_auth(AuthConfig::basic("bob", "hunter2").for_hosts(["files.example.com"]))
+        .validate_settings()
+        .is_ok());
+}
+
+#[test]
+fn test_url_prefix_matching() {
+    let prefix = "https://artifacts.example.com";
+    assert!(config::url_prefix_matches(prefix, "https://artifacts.example.com/a.bin"));
+    assert!(config::url_prefix_matches(prefix, "https://ARTIFACTS.example.com:443/a.bin"));
+    // 形似的主机、其他端口和协议都不算
+    assert!(!config::url_prefix_matches(prefix, "https://artifacts.example.com.evil.net/a.bin"));
+    assert!(!config::url_prefix_matches(prefix, "https://artifacts.example.com:8443/a.bin"));
+    assert!(!config::url_prefix_matches(prefix, "http://artifacts.example.com/a.bin"));
+    // 路径按段匹配
+    let prefix = "https://artifacts.example.com/files";
+    assert!(config::url_prefix_matches(prefix, "https://artifacts.example.com/files"));
+    assert!(config::url_prefix_matches(prefix, "https://artifacts.example.com/files/a.bin"));
+    assert!(!config::url_prefix_matches(prefix, "https://artifacts.example.com/files2/a.bin"));
+    assert!(!config::url_prefix_matches("artifacts.example.com", "https://artifacts.example.com/"));
+    assert!(matches!(
+        Config::new().with_url_header("artifacts.example.com", "X-Token", "t").validate_settings(),
+        Err(ConfigError::InvalidHeader(_))
+    ));
+
+    // 代理覆盖用同样的规则，较长的前缀优先
+    let proxy = ProxyConfig::new("http://proxy.corp:3128")
+        .with_override("https://mirror.example.com", "direct")
+        .with_override("https://mirror.example.com/private/", "socks5://127.0.0.1:1080");
+    assert_eq!(proxy.override_for("https://mirror.example.com/a.bin"), Some("direct"));
+    assert_eq!(
+        proxy.override_for("https://mirror.example.com/private/a.bin"),
+        Some("socks5://127.0.0.1:1080")
+    );
+    assert_eq!(proxy.override_for("https://mirror.example.com/private2/a.bin"), Some("direct"));
+    assert_eq!(proxy.override_for("https://mirror.example.com.evil.net/a.bin"), None);
+    assert!(matches!(
+        Config::new()
+            .with_proxy(ProxyConfig::new("http://proxy.corp:3128").with_override("mirror.example.com", "direct"))
+            .validate_settings(),
+        Err(ConfigError::InvalidProxy(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_request_headers_cookies_and_credentials() {
+    let server = TestServer::start().await;
+    let body = sample_body(1_000);
+    server.route("/private/a.bin", Route::new(body.clone()));
+    server.route("/public/b.bin", Route::new(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cookie_file = temp_dir.path().join("cookies.txt");
+    std::fs::write(
+        &cookie_file,
+        "# Netscape HTTP Cookie File\n\
+         127.0.0.1\tFALSE\t/private\tFALSE\t0\tsession\tabc123\n\
+         127.0.0.1\tFALSE\t/\tFALSE\t1\texpired\tgone\n",
+    )
+    .unwrap();
+
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("downloads"))
+        .with_cache_dir(temp_dir.path().join("cache"))
+        .with_user_agent("multhreadown-test/1.0")
+        .with_header("X-Global", "1")
+        .with_url_header(server.url("/private/"), "X-Global", "2")
+        .with_auth(AuthConfig::bearer("artifact-token").for_hosts(["127.0.0.1"]))
+        .with_cookie_file(&cookie_file);
+    let downloader = Downloader::builder().with_config(config).build().await.unwrap();
+    let report = downloader
+        .download_many([
+            DownloadRequest::new(server.url("/private/a.bin")).with_header("X-Request", "a"),
+            DownloadRequest::new(server.url("/public/b.bin")),
+        ])
+        .await
+        .unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let private = &server.requests_for("GET", "/private/a.bin")[0];
+    assert_eq!(private.header("user-agent"), Some("multhreadown-test/1.0"));
+    assert_eq!(private.header("x-global"), Some("2"));
+    assert_eq!(private.header("x-request"), Some("a"));
+    assert_eq!(private.header("authorization"), Some("Bearer artifact-token"));
+    assert_eq!(private.header("cookie"), Some("session=abc123"));
+    let public = &server.requests_for("GET", "/public/b.bin")[0];
+    assert_eq!(public.header("x-global"), Some("1"));
+    assert_eq!(public.header("x-request"), None);
+    assert_eq!(public.header("cookie"), None);
+
+    // 没有 `[auth]` 时使用 `.netrc` 中的用户名和密码
+    let netrc_file = temp_dir.path().join("netrc");
+    std::fs::write(&netrc_file, "machine 127.0.0.1 login alice password s3cret\n").unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("netrc-downloads"))
+        .with_urls([server.url("/public/b.bin")])
+        .with_netrc(Some(netrc_file));
+    downloader::download_all_files(config).await.unwrap();
+    let last = server.requests_for("GET", "/public/b.bin").pop().unwrap();
+    assert_eq!(last.header("authorization"), Some("Basic YWxpY2U6czNjcmV0"));
+}
+
+#[tokio::test]
+async fn test_proxy_routing() {
+    // 代理收到的请求行是完整的 URL，按完整 URL 注册路由即可充当 HTTP 代理
+    let origin = TestServer::start().await;
+    let proxy = TestServer::start().await;
+    let body = sample_body(1_500);
+    for path in ["/ext/a.bin", "/int/b.bin", "/int/c.bin"] {
+        origin.route(path, Route::new(body.clone()));
+        proxy.route(&origin.url(path), Route::new(body.clone()));
+    }
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("downloads"))
+        .with_cache_dir(temp_dir.path().join("cache"))
+        .with_proxy(
+            ProxyConfig::new(proxy.url(""))
+                .with_credentials("alice", "s3cret")
+                .with_override(origin.url("/int/"), "direct"),
+        );
+    let downloader = Downloader::builder().with_config(config).build().await.unwrap();
+    let report = downloader
+        .download_many([
+            DownloadRequest::new(origin.url("/ext/a.bin")),
+            DownloadRequest::new(origin.url("/int/b.bin")),
+            DownloadRequest::new(origin.url("/int/c.bin")).with_proxy(proxy.url("")),
+        ])
+        .await
+        .unwrap();
+    assert!(report.is_success(), "{}", report);
+
+    let proxied = proxy.requests_for("GET", &origin.url("/ext/a.bin"));
+    assert_eq!(proxied.len(), 1);
+    assert_eq!(
+        proxied[0].header("proxy-authorization"),
+        Some("Basic YWxpY2U6czNjcmV0")
+    );
+    assert!(origin.requests_for("GET", "/ext/a.bin").is_empty());
+    // 前缀覆盖为直连，单个 URL 的代理又优先于前缀覆盖
+    assert_eq!(origin.requests_for("GET", "/int/b.bin").len(), 1);
+    assert!(origin.requests_for("GET", "/int/c.bin").is_empty());
+    assert_eq!(proxy.requests_for("GET", &origin.url("/int/c.bin")).len(), 1);
+
+    // `no_proxy` 中的主机直连
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("direct"))
+        .with_urls([origin.url("/ext/a.bin")])
+        .with_proxy(ProxyConfig::new(proxy.url("")).with_no_proxy(["127.0.0.1"]));
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(origin.requests_for("GET", "/ext/a.bin").len(), 1);
+
+    assert!(matches!(
+        Config::new()
+            .with_proxy(ProxyConfig::new("ftp://proxy.example.com"))
+            .validate_settings(),
+        Err(ConfigError::InvalidProxy(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_tls_settings() {
+    // 自签名证书同时充当根证书和客户端证书
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let cert = fixtures.join("client.pem");
+    let key = fixtures.join("client-key.pem");
+    let temp_dir = tempfile::tempdir().unwrap();
+    let combined = temp_dir.path().join("combined.pem");
+    let mut pem = std::fs::read(&cert).unwrap();
+    pem.extend(std::fs::read(&key).unwrap());
+    std::fs::write(&combined, pem).unwrap();
+
+    let server = TestServer::start().await;
+    let body = sample_body(2_000);
+    server.route("/file.bin", Route::new(body.clone()));
+
+    let tls = TlsConfig::default()
+        .with_ca_file(&cert)
+        .with_client_cert(&cert, Some(key.clone()))
+        .with_min_version("1.2")
+        .with_danger_accept_invalid_certs(true);
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("downloads"))
+        .with_urls([server.url("/file.bin")])
+        .with_tls(tls);
+    config.validate().unwrap();
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(
+        std::fs::read(temp_dir.path().join("downloads/file.bin")).unwrap(),
+        body
+    );
+
+    // 证书和私钥在同一个文件中
+    let config = Config::new().with_tls(TlsConfig::default().with_client_cert(&combined, None));
+    assert!(Downloader::builder().with_config(config).build().await.is_ok());
+
+    // 无法读取或解析的文件在构建客户端时报错
+    let garbage = temp_dir.path().join("garbage.pem");
+    std::fs::write(&garbage, "not a certificate").unwrap();
+    for tls in [
+        TlsConfig::default().with_ca_file(&garbage),
+        TlsConfig::default().with_ca_file(temp_dir.path().join("missing.pem")),
+        TlsConfig::default().with_client_cert(&cert, Some(garbage.clone())),
+    ] {
+        let config = Config::new().with_tls(tls);
+        assert!(matches!(
+            Downloader::builder().with_config(config).build().await,
+            Err(DownloadError::ConfigError(_))
+        ));
+    }
+
+    for tls in [
+        TlsConfig::default().with_min_version("1.3"),
+        TlsConfig::default().with_min_version("tls1.2"),
+        TlsConfig {
+            client_key: Some(key),
+            ..TlsConfig::default()
+        },
+    ] {
+        assert!(matches!(
+            Config::new().with_tls(tls).validate_settings(),
+            Err(ConfigError::InvalidTls(_))
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_read_timeout_resumes_stalled_transfers() {
+    let server = TestServer::start().await;
+    let body = sample_body(64 * 1024);
+    server.route("/single.bin", Route::ranged(body.clone()).stall_first(1));
+    server.route("/segmented.bin", Route::ranged(body.clone()).stall_first(1));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(1)
+        .with_retry_delay(Duration::ZERO)
+        .with_read_timeout(Duration::from_secs(1))
+        .with_urls([server.url("/single.bin")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(std::fs::read(temp_dir.path().join("single.bin")).unwrap(), body);
+    // 停顿的连接被放弃后，从已写入的位置续传
+    let gets = server.requests_for("GET", "/single.bin");
+    assert_eq!(gets.len(), 2);
+    assert_eq!(gets[1].header("range"), Some("bytes=32768-"));
+
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_segments(2)
+        .with_chunk_size(1024)
+        .with_retry_delay(Duration::ZERO)
+        .with_read_timeout(Duration::from_secs(1))
+        .with_urls([server.url("/segmented.bin")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert_eq!(
+        std::fs::read(temp_dir.path().join("segmented.bin")).unwrap(),
+        body
+    );
+    assert_eq!(server.requests_for("GET", "/segmented.bin").len(), 3);
+}
+
+#[tokio::test]
+async fn test_retry_limit_counts_consecutive_failures() {
+    let server = TestServer::start().await;
+    let body = sample_body(64 * 1024);
+    // 每次连接都在传完一半时断开，但每次都有进展
+    server.route("/single.bin", Route::ranged(body.clone()).truncate_first(3));
+    server.route("/segmented.bin", Route::ranged(body.clone()).truncate_first(4));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    for (path, segments) in [("/single.bin", 1), ("/segmented.bin", 2)] {
+        let config = Config::new()
+            .with_download_dir(temp_dir.path())
+            .with_segments(segments)
+            .with_chunk_size(1024)
+            .with_retry_attempts(1)
+            .with_retry_delay(Duration::ZERO)
+            .with_urls([server.url(path)]);
+        let report = downloader::download_all_files(config).await.unwrap();
+        assert!(report.is_success(), "{}", report);
+        assert_eq!(std::fs::read(temp_dir.path().join(&path[1..])).unwrap(), body);
+    }
+    assert_eq!(server.requests_for("GET", "/single.bin").len(), 4);
+}
+
+#[tokio::test]
+async fn test_file_and_batch_timeouts() {
+    let server = TestServer::start().await;
+    let body = sample_body(16 * 1024);
+    server.route("/stalled.bin", Route::new(body.clone()).stall_first(usize::MAX));
+    server.route("/fine.bin", Route::new(body.clone()));
+
+    // 没有读取超时时，单个文件的总时限仍会结束停顿的下载，且不会重试
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("file"))
+        .with_segments(1)
+        .with_read_timeout(Duration::ZERO)
+        .with_file_timeout(Some(Duration::from_secs(1)))
+        .with_urls([server.url("/stalled.bin"), server.url("/fine.bin")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(matches!(
+        &report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::FileTimeout(0, limit)) if limit.as_secs() == 1
+    ));
+    assert!(matches!(report.entries[1].outcome, DownloadOutcome::Completed));
+    assert_eq!(server.requests_for("GET", "/stalled.bin").len(), 1);
+
+    // 整批超时后，正在进行和尚未开始的下载都记为失败
+    let config = Config::new()
+        .with_download_dir(temp_dir.path().join("batch"))
+        .with_threads(1)
+        .with_segments(1)
+        .with_read_timeout(Duration::ZERO)
+        .with_batch_timeout(Some(Duration::from_secs(1)))
+        .with_urls([server.url("/stalled.bin"), server.url("/fine.bin")]);
+    let report = downloader::download_all_files(config).await.unwrap();
+    for entry in &report.entries {
+        assert!(
+            matches!(entry.outcome, DownloadOutcome::Failed(DownloadError::BatchTimeout(_))),
+            "{:?}",
+            entry.outcome
+        );
+    }
+    // 第二个 URL 开始时本批已超时，不再发出请求
+    assert_eq!(server.requests_for("GET", "/fine.bin").len(), 1);
+
+    assert!(matches!(
+        Config::new()
+            .with_file_timeout(Some(Duration::ZERO))
+            .validate_settings(),
+        Err(ConfigError::InvalidTimeout(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_retry_classification_and_host_backoff() {
+    let server = TestServer::start().await;
+    let body = sample_body(4_000);
+    server.route("/forbidden.bin", Route::new(body.clone()).fail_first(usize::MAX, 403));
+    server.route("/flaky.bin", Route::new(body.clone()).fail_first(1, 502));
+    server.route(
+        "/throttled.bin",
+        Route::new(body.clone()).fail_first(1, 429).retry_after("1"),
+    );
+    server.route(
+        "/busy.bin",
+        Route::new(body.clone()).fail_first(usize::MAX, 503).retry_after("1"),
+    );
+    server.route("/next.bin", Route::new(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = |name: &str| {
+        Config::new()
+            .with_download_dir(temp_dir.path().join(name))
+            .with_threads(1)
+            .with_segments(1)
+            .with_retry_delay(Duration::ZERO)
+    };
+
+    // 403 不重试，502 重试后成功
+    let report = downloader::download_all_files(
+        config("status").with_urls([server.url("/forbidden.bin"), server.url("/flaky.bin")]),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::HttpError(_, 403, _))
+    ));
+    assert!(matches!(report.entries[1].outcome, DownloadOutcome::Completed));
+    assert_eq!(server.requests_for("GET", "/forbidden.bin").len(), 1);
+    assert_eq!(server.requests_for("GET", "/flaky.bin").len(), 2);
+
+    // 重试等到 Retry-After 指定的时间之后
+    let started = std::time::Instant::now();
+    let report = downloader::download_all_files(
+        config("retry-after").with_urls([server.url("/throttled.bin")]),
+    )
+    .await
+    .unwrap();
+    assert!(report.is_success(), "{}", report);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(server.requests_for("GET", "/throttled.bin").len(), 2);
+
+    // 一个 URL 被限流后，同一主机上的其他 URL 也要等待退避结束
+    let started = std::time::Instant::now();
+    let report = downloader::download_all_files(
+        config("host")
+            .with_retry_attempts(0)
+            .with_urls([server.url("/busy.bin"), server.url("/next.bin")]),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        report.entries[0].outcome,
+        DownloadOutcome::Failed(DownloadError::HttpError(_, 503, _))
+    ));
+    assert!(matches!(report.entries[1].outcome, DownloadOutcome::Completed));
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[test]
+fn test_retry_after_parsing() {
+    use multhreadown::hosts;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+
+    let parse = |value: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        hosts::retry_after(&headers)
+    };
+    assert_eq!(parse("120"), Some(Duration::from_secs(120)));
+    assert_eq!(parse("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+    let later = std::time::SystemTime::now() + Duration::from_secs(300);
+    let wait = parse(&httpdate::fmt_http_date(later)).unwrap();
+    assert!(wait > Duration::from_secs(290) && wait <= Duration::from_secs(300));
+    assert_eq!(parse("soon"), None);
+    assert_eq!(hosts::retry_after(&HeaderMap::new()), None);
+
+    // 过长的等待时间被限制在上限以内，异常大的值也不会溢出
+    let capped = |value: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(value).unwrap());
+        hosts::capped_retry_after(&headers, hosts::MAX_RETRY_AFTER)
+    };
+    assert_eq!(capped("120"), Some(Duration::from_secs(120)));
+    assert_eq!(capped("86400"), Some(hosts::MAX_RETRY_AFTER));
+    assert_eq!(capped(&u64::MAX.to_string()), Some(hosts::MAX_RETRY_AFTER));
+    let backoff = hosts::HostBackoff::new();
+    backoff.throttle("example.com", |_| capped(&u64::MAX.to_string()).unwrap());
+    assert!(backoff.remaining("example.com").unwrap() <= hosts::MAX_RETRY_AFTER);
+
+    assert!(hosts::is_retryable_status(429));
+    assert!(hosts::is_retryable_status(503));
+    assert!(!hosts::is_retryable_status(404));
+    assert!(!hosts::is_retryable_status(403));
+}
+
+#[tokio::test]
+async fn test_connections_per_host_limit() {
+    let server = TestServer::start().await;
+    let body = sample_body(16 * 1024);
+    let urls: Vec<_> = (0..4)
+        .map(|i| {
+            let path = format!("/mirror/{}.bin", i);
+            server.route(&path, Route::ranged(body.clone()).delay(Duration::from_millis(30)));
+            server.url(&path)
+        })
+        .collect();
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_threads(4)
+        .with_segments(4)
+        .with_chunk_size(1024)
+        .with_max_connections_per_host(Some(2))
+        .with_urls(urls);
+    let report = downloader::download_all_files(config).await.unwrap();
+    assert!(report.is_success(), "{}", report);
+    for i in 0..4 {
+        assert_eq!(std::fs::read(temp_dir.path().join(format!("{}.bin", i))).unwrap(), body);
+    }
+    // 探测和各个分段都计入同一主机的连接数
+    assert_eq!(server.max_concurrent(), 2);
+
+    assert!(matches!(
+        Config::new()
+            .with_max_connections_per_host(Some(0))
+            .validate_settings(),
+        Err(ConfigError::InvalidConnections(0))
+    ));
+}
+
+/// 按顺序记录开始、完成和失败的 URL
+#[derive(Default)]
+struct EventLog {
+    events: std::sync::Mutex<Vec<(&'static str, String)>>,
+}
+
+#[async_trait]
+impl DownloadEventHandler for EventLog {
+    async fn on_download_start(&self, url: &str) {
+        self.events.lock().unwrap().push(("start", url.to_string()));
+    }
+
+    async fn on_download_progress(&self, _url: &str, _progress: f64) {}
+
+    async fn on_download_complete(&self, url: &str) {
+        self.events.lock().unwrap().push(("complete", url.to_string()));
+    }
+
+    async fn on_download_error(&self, url: &str, _error: &DownloadError) {
+        self.events.lock().unwrap().push(("error", url.to_string()));
+    }
+}
+
+#[tokio::test]
+async fn test_scheduler_interleaves_hosts() {
+    // 慢主机上的文件排在前面，另一个主机的文件排在最后
+    let slow = TestServer::start().await;
+    let fast = TestServer::start().await;
+    let body = sample_body(4_000);
+    slow.route("/a.bin", Route::new(body.clone()).stall_first(usize::MAX));
+    slow.route("/b.bin", Route::new(body.clone()).stall_first(usize::MAX));
+    fast.route("/urgent.bin", Route::new(body.clone()));
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let config = Config::new()
+        .with_download_dir(temp_dir.path())
+        .with_threads(2)
+        .with_segments(1)
+        .with_read_timeout(Duration::ZERO)
+        .with_file_timeout(Some(Duration::from_secs(1)))
+        .with_max_connections_per_host(Some(1));
+    let log = Arc::new(EventLog::default());
+    let downloader = Downloader::builder()
+        .with_config(config)
+        .with_event_handler(log.clone())
+        .build()
+        .await
+        .unwrap();
+    let report = downloader
+        .download_many([
+            slow.url("/a.bin"),
+            slow.url("/b.bin"),
+            fast.url("/urgent.bin"),
+        ])
+        .await
+        .unwrap();
+
+    assert!(matches!(report.entries[2].outcome, DownloadOutcome::Completed));
+    // 慢主机已达到上限，空闲的工作者先开始另一个主机的文件
+    let events = log.events.lock().unwrap().clone();
+    let position = |event: &str, url: &str| {
+        events
+            .iter()
+            .position(|(e, u)| *e == event && u == url)
+            .unwrap()
+    };
+    assert!(position("complete", &fast.url("/urgent.bin")) < position("start", &slow.url("/b.bin")));
+    assert_eq!(events[0], ("start", slow.url("/a.bin")));
+}